@@ -24,31 +24,61 @@ mod day_23;
 mod day_24;
 mod day_25;
 
-pub fn run(day: u8) {
-    match day {
+use crate::Cli;
+
+pub fn run(cli: &Cli) {
+    match cli.day {
         1 => day_01::run(),
-        2 => day_02::run(),
-        3 => day_03::run(),
-        4 => day_04::run(),
-        5 => day_05::run(),
-        6 => day_06::run(),
-        7 => day_07::run(),
+        2 => day_02::run(cli.tolerance),
+        3 => day_03::run(&cli.extra_instructions, cli.audit),
+        4 => day_04::run(
+            &cli.words,
+            &cli.patterns,
+            cli.list_matches,
+            cli.highlight.as_deref(),
+        ),
+        5 => day_05::run(cli.violations.as_deref()),
+        6 => day_06::run(&cli.show_loop),
+        7 => day_07::run(&cli.operators),
         8 => day_08::run(),
         9 => day_09::run(),
         10 => day_10::run(),
         11 => day_11::run(),
-        12 => day_12::run(),
-        13 => day_13::run(),
+        12 => day_12::run(cli.sort_by.as_deref(), cli.image.as_deref()),
+        13 => day_13::run(&cli.button_costs),
         14 => day_14::run(),
         15 => day_15::run(),
-        16 => day_16::run(),
+        16 => day_16::run(
+            day_16::Rules::new(
+                cli.step_cost,
+                cli.turn_cost,
+                cli.u_turn_cost,
+                cli.diagonal,
+                &cli.start_facing,
+                cli.end_facing.as_deref(),
+            ),
+            cli.k,
+        ),
         17 => day_17::run(),
-        18 => day_18::run(),
+        18 => day_18::run(cli.width, cli.height, cli.bytes),
         19 => day_19::run(),
-        20 => day_20::run(),
+        20 => day_20::run(cli.cheat_len, cli.min_save),
         21 => day_21::run(),
-        22 => day_22::run(),
-        23 => day_23::run(),
+        22 => day_22::run(cli.top, cli.secret_steps, cli.cycle_length),
+        23 => day_23::run(
+            cli.list_cliques,
+            cli.k.map(|k| {
+                day_23::CliqueQuery::new(
+                    k,
+                    cli.prefix.as_deref(),
+                    cli.name_regex.as_deref(),
+                    &cli.contains,
+                    cli.list_k_cliques,
+                )
+            }),
+            cli.emit.as_deref(),
+            cli.stats,
+        ),
         24 => day_24::run(),
         25 => day_25::run(),
         0 | 26..=u8::MAX => panic!("invalid day"),
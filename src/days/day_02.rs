@@ -2,7 +2,7 @@ use std::io::{BufWriter, Write};
 
 use aoclib_rs::{prep_io, printwriteln, split_and_parse};
 
-pub fn run() {
+pub fn run(tolerance: usize) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 2).unwrap();
     let reports = contents
@@ -11,13 +11,13 @@ pub fn run() {
         .collect();
 
     part1(&mut writer, &reports);
-    part2(&mut writer, &reports);
+    part2(&mut writer, &reports, tolerance);
 }
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, reports: &Vec<Vec<u8>>) {
     let mut safe = 0;
     for report in reports {
-        if is_safe_as_is(report) {
+        if first_bad_step(report).is_none() {
             safe += 1;
         }
     }
@@ -25,18 +25,45 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, reports: &Vec<Vec<u8>>) {
     printwriteln!(writer, "part 1: {}", safe).unwrap();
 }
 
-fn part2<W: Write>(writer: &mut BufWriter<W>, reports: &Vec<Vec<u8>>) {
+fn part2<W: Write>(writer: &mut BufWriter<W>, reports: &Vec<Vec<u8>>, tolerance: usize) {
     let mut safe = 0;
     for report in reports {
-        if is_safe_part2(report) {
-            safe += 1;
+        match dampen(report, tolerance) {
+            Ok(removed) => {
+                safe += 1;
+                match removed.len() {
+                    0 => println!("{:?}: safe as is", report),
+                    _ => println!(
+                        "{:?}: safe without level{} {}",
+                        report,
+                        if removed.len() == 1 { "" } else { "s" },
+                        removed
+                            .iter()
+                            .map(|i| format!("{} ({})", i, report[*i]))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                }
+            }
+            Err(i) => println!(
+                "{:?}: unsafe - {} -> {} at index {} is the first bad step, and removing up to {} \
+                 level{} can't fix every one",
+                report,
+                report[i - 1],
+                report[i],
+                i,
+                tolerance,
+                if tolerance == 1 { "" } else { "s" }
+            ),
         }
     }
 
     printwriteln!(writer, "part 2: {}", safe).unwrap();
 }
 
-fn is_safe_as_is(report: &[u8]) -> bool {
+/// The index of the level at the end of the first step that's too big, too small or the wrong
+/// way, taking the way to go from the first step.
+fn first_bad_step(report: &[u8]) -> Option<usize> {
     let mut increasing = false;
     for i in 1..report.len() {
         if i == 1 && report[i] > report[i - 1] {
@@ -50,26 +77,75 @@ fn is_safe_as_is(report: &[u8]) -> bool {
             && report[i - 1] - report[i] <= 3
             && report[i] != report[i - 1];
         if !increasing_and_safe && !decreasing_and_safe {
-            return false;
+            return Some(i);
         }
     }
 
-    true
+    None
+}
+
+/// The fewest levels to remove, up to `tolerance`, to make the report safe. Otherwise, the
+/// index of its first bad step.
+fn dampen(report: &[u8], tolerance: usize) -> Result<Vec<usize>, usize> {
+    // with no steps there's nothing to go wrong
+    if report.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    [true, false]
+        .iter()
+        .filter_map(|&increasing| fewest_removals(report, increasing, tolerance))
+        .min_by_key(|removed| removed.len())
+        .ok_or_else(|| first_bad_step(report).unwrap())
 }
 
-fn is_safe_part2(report: &[u8]) -> bool {
-    if is_safe_as_is(report) {
-        return true;
+/// The fewest levels to remove, up to `tolerance`, so the rest go safely up or down. In one
+/// pass, finds for each level the fewest removals so far if it's kept, having kept one of the
+/// `tolerance + 1` levels before it (or none), so this takes O(n * tolerance).
+fn fewest_removals(report: &[u8], increasing: bool, tolerance: usize) -> Option<Vec<usize>> {
+    let n = report.len();
+    let step_is_safe = |a: u8, b: u8| {
+        if increasing {
+            b > a && b - a <= 3
+        } else {
+            a > b && a - b <= 3
+        }
+    };
+
+    let mut removals = vec![usize::MAX; n];
+    let mut prev = vec![None; n];
+    for i in 0..n {
+        if i <= tolerance {
+            removals[i] = i;
+        }
+
+        for p in i.saturating_sub(tolerance + 1)..i {
+            if removals[p] == usize::MAX || !step_is_safe(report[p], report[i]) {
+                continue;
+            }
+
+            let r = removals[p] + (i - p - 1);
+            if r < removals[i] {
+                removals[i] = r;
+                prev[i] = Some(p);
+            }
+        }
     }
 
-    for i in 0..report.len() {
-        let mut report = report.to_owned();
-        report.remove(i);
+    // the last level kept, with everything after it removed too
+    let mut last = (n.saturating_sub(tolerance + 1)..n)
+        .filter(|&i| removals[i] != usize::MAX)
+        .min_by_key(|&i| removals[i] + (n - 1 - i))
+        .filter(|&i| removals[i] + (n - 1 - i) <= tolerance)?;
 
-        if is_safe_as_is(&report) {
-            return true;
+    let mut kept = vec![false; n];
+    loop {
+        kept[last] = true;
+        match prev[last] {
+            Some(p) => last = p,
+            None => break,
         }
     }
 
-    false
+    Some((0..n).filter(|&i| !kept[i]).collect())
 }
@@ -2,72 +2,221 @@ use std::io::{BufWriter, Write};
 
 use aoclib_rs::{prep_io, printwriteln};
 
-use {once_cell::sync::Lazy, regex::Regex};
+#[derive(Copy, Clone, PartialEq)]
+enum Effect {
+    Product,
+    Sum,
+    Enable,
+    Disable,
+}
+
+/// An instruction that can be found in the corrupted memory: its name, followed by `arity`
+/// numbers of 1 to 3 digits in brackets, separated by commas.
+struct Instruction {
+    name: String,
+    arity: usize,
+    effect: Effect,
+}
+
+impl Instruction {
+    fn new(name: &str, arity: usize, effect: Effect) -> Instruction {
+        Instruction {
+            name: name.into(),
+            arity,
+            effect,
+        }
+    }
 
-pub fn run() {
+    /// Parses `name:effect[:arity]`, where the effect is product, sum, enable or disable. Product
+    /// and sum take 2 numbers unless told otherwise, and enable and disable take none.
+    fn parse(spec: &str) -> Instruction {
+        let sp: Vec<&str> = spec.split(':').collect();
+        let (effect, default_arity) = match sp.get(1) {
+            Some(&"product") => (Effect::Product, 2),
+            Some(&"sum") => (Effect::Sum, 2),
+            Some(&"enable") => (Effect::Enable, 0),
+            Some(&"disable") => (Effect::Disable, 0),
+            _ => panic!("bad instruction {}, expected name:effect[:arity]", spec),
+        };
+        let arity = sp.get(2).map_or(default_arity, |a| a.parse().unwrap());
+
+        Instruction::new(sp[0], arity, effect)
+    }
+}
+
+/// A valid instruction, and where it is in the memory as byte offsets.
+struct Token {
+    instruction: usize,
+    args: Vec<i64>,
+    start: usize,
+    end: usize,
+}
+
+/// A stretch of memory in which instructions are all enabled or all disabled.
+struct Span {
+    enabled: bool,
+    start: usize,
+    end: usize,
+}
+
+pub fn run(extra_instructions: &[String], audit: bool) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 3).unwrap();
+    let memory = contents.join("\n");
 
-    part1(&mut writer, &contents);
-    part2(&mut writer, &contents);
-}
+    let mut instructions = vec![
+        Instruction::new("mul", 2, Effect::Product),
+        Instruction::new("do", 0, Effect::Enable),
+        Instruction::new("don't", 0, Effect::Disable),
+    ];
+    instructions.extend(extra_instructions.iter().map(|s| Instruction::parse(s)));
+    let tokens = lex(&memory, &instructions);
 
-fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &Vec<&str>) {
-    let mut total = 0;
-    for line in contents {
-        total += find_and_eval_muls_part1(line);
+    part1(&mut writer, &tokens, &instructions);
+    part2(&mut writer, &tokens, &instructions);
+
+    if audit {
+        print_audit(&memory, &tokens, &instructions);
     }
+}
 
+fn part1<W: Write>(writer: &mut BufWriter<W>, tokens: &[Token], instructions: &[Instruction]) {
+    let (total, _) = execute(tokens, instructions, false);
     printwriteln!(writer, "part 1: {}", total).unwrap();
 }
 
-fn part2<W: Write>(writer: &mut BufWriter<W>, contents: &Vec<&str>) {
-    let mut total = 0;
-    let mut doo = true;
-    for line in contents {
-        let i;
-        (i, doo) = find_and_eval_muls_part2(line, doo);
-        total += i;
+fn part2<W: Write>(writer: &mut BufWriter<W>, tokens: &[Token], instructions: &[Instruction]) {
+    let (total, _) = execute(tokens, instructions, true);
+    printwriteln!(writer, "part 2: {}", total).unwrap();
+}
+
+/// Picks the valid instructions out of the memory, left to right. Anything that isn't part of
+/// one is skipped, a byte at a time.
+fn lex(memory: &str, instructions: &[Instruction]) -> Vec<Token> {
+    let bytes = memory.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match instructions
+            .iter()
+            .enumerate()
+            .find_map(|(n, instruction)| lex_at(bytes, i, instruction).map(|t| (n, t)))
+        {
+            Some((instruction, (args, end))) => {
+                tokens.push(Token {
+                    instruction,
+                    args,
+                    start: i,
+                    end,
+                });
+                i = end;
+            }
+            None => i += 1,
+        }
     }
 
-    printwriteln!(writer, "part 2: {}", total).unwrap();
+    tokens
 }
 
-fn find_and_eval_muls_part1(line: &str) -> i32 {
-    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"mul\((\d{1,3}),(\d{1,3})\)").unwrap());
-    let caps = RE.captures_iter(line);
+/// The instruction's arguments and where it ends, if it's at `start`.
+fn lex_at(bytes: &[u8], start: usize, instruction: &Instruction) -> Option<(Vec<i64>, usize)> {
+    let mut i = start;
+    let expect = |s: &[u8], i: &mut usize| -> Option<()> {
+        bytes[*i..].starts_with(s).then(|| *i += s.len())
+    };
 
-    let mut total = 0;
-    for cap in caps {
-        let i1 = cap[1].parse::<i32>().unwrap();
-        let i2 = cap[2].parse::<i32>().unwrap();
+    expect(instruction.name.as_bytes(), &mut i)?;
+    expect(b"(", &mut i)?;
+
+    let mut args = Vec::with_capacity(instruction.arity);
+    for a in 0..instruction.arity {
+        if a > 0 {
+            expect(b",", &mut i)?;
+        }
 
-        total += i1 * i2;
+        let digits = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if !(1..=3).contains(&digits) {
+            return None;
+        }
+        args.push(
+            bytes[i..(i + digits)]
+                .iter()
+                .fold(0, |acc, b| acc * 10 + (b - b'0') as i64),
+        );
+        i += digits;
     }
 
-    total
-}
+    expect(b")", &mut i)?;
 
-fn find_and_eval_muls_part2(line: &str, mut doo: bool) -> (i32, bool) {
-    static RE: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"(mul\((\d{1,3}),(\d{1,3})\))|(do\(\))|(don't\(\))").unwrap());
-    let caps = RE.captures_iter(line);
+    Some((args, i))
+}
 
+/// Adds up what the instructions come to, obeying enable and disable only if `toggles` is set.
+/// Also returns the enabled and disabled spans, which end at the last instruction.
+fn execute(tokens: &[Token], instructions: &[Instruction], toggles: bool) -> (i64, Vec<Span>) {
     let mut total = 0;
-    for cap in caps {
-        match &cap[0] {
-            "do()" => doo = true,
-            "don't()" => doo = false,
-            _ => {
-                if doo {
-                    let i1 = cap[2].parse::<i32>().unwrap();
-                    let i2 = cap[3].parse::<i32>().unwrap();
-
-                    total += i1 * i2;
+    let mut spans = vec![Span {
+        enabled: true,
+        start: 0,
+        end: 0,
+    }];
+    for token in tokens {
+        let span = spans.last_mut().unwrap();
+        span.end = token.end;
+
+        match instructions[token.instruction].effect {
+            Effect::Product if span.enabled => total += token.args.iter().product::<i64>(),
+            Effect::Sum if span.enabled => total += token.args.iter().sum::<i64>(),
+            Effect::Product | Effect::Sum => {}
+            Effect::Enable | Effect::Disable if !toggles => {}
+            Effect::Enable | Effect::Disable => {
+                let enabled = instructions[token.instruction].effect == Effect::Enable;
+                if enabled != span.enabled {
+                    span.end = token.start;
+                    spans.push(Span {
+                        enabled,
+                        start: token.start,
+                        end: token.end,
+                    });
                 }
             }
         }
     }
 
-    (total, doo)
+    (total, spans)
+}
+
+/// Lists each instruction with its byte offsets, and whether it counted under the part 2 rules,
+/// followed by the enabled and disabled spans.
+fn print_audit(memory: &str, tokens: &[Token], instructions: &[Instruction]) {
+    let (_, mut spans) = execute(tokens, instructions, true);
+    spans.last_mut().unwrap().end = memory.len();
+    let mut s = 0;
+    for token in tokens {
+        while spans[s].end <= token.start {
+            s += 1;
+        }
+
+        let counted = match instructions[token.instruction].effect {
+            Effect::Product | Effect::Sum if spans[s].enabled => "counted",
+            Effect::Product | Effect::Sum => "skipped",
+            Effect::Enable | Effect::Disable => "",
+        };
+        println!(
+            "{:>6}..{:<6} {} {}",
+            token.start,
+            token.end,
+            &memory[token.start..token.end],
+            counted
+        );
+    }
+
+    for span in &spans {
+        println!(
+            "{} {}..{}",
+            if span.enabled { "enabled" } else { "disabled" },
+            span.start,
+            span.end
+        );
+    }
 }
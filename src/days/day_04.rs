@@ -2,80 +2,256 @@ use std::io::{BufWriter, Write};
 
 use aoclib_rs::{pad, prep_io, printwriteln, usize_plus_i};
 
-const PADDING: usize = 4;
+// the ways a word can run, as (row, column) steps
+const DIRECTIONS: [(&str, i32, i32); 8] = [
+    ("right", 0, 1),
+    ("down-right", 1, 1),
+    ("down", 1, 0),
+    ("down-left", 1, -1),
+    ("left", 0, -1),
+    ("up-left", -1, -1),
+    ("up", -1, 0),
+    ("up-right", -1, 1),
+];
 
-pub fn run() {
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+// rotations are clockwise, and flipping is left to right before rotating
+const SYMMETRIES: [&str; 8] = [
+    "as given",
+    "rotated 90",
+    "rotated 180",
+    "rotated 270",
+    "flipped",
+    "flipped, rotated 90",
+    "flipped, rotated 180",
+    "flipped, rotated 270",
+];
+
+/// Something to search for: the letter needed at each (row, column) offset from where it
+/// starts, and every distinct way it can be turned round on the grid.
+struct Shape {
+    name: String,
+    cells: Vec<(i32, i32, u8)>,
+    orientations: Vec<(&'static str, [[i32; 2]; 2])>,
+}
+
+impl Shape {
+    /// A word, which can run in any of the 8 directions.
+    fn word(word: &str) -> Shape {
+        let cells = word
+            .bytes()
+            .enumerate()
+            .map(|(i, b)| (0, i as i32, b))
+            .collect();
+        // only the column offset is ever non-zero, so only the second column matters
+        let orientations = DIRECTIONS
+            .iter()
+            .map(|&(name, dr, dc)| (name, [[0, dr], [0, dc]]))
+            .collect();
+
+        Shape::new(word.into(), cells, orientations)
+    }
+
+    /// A 2D pattern with rows separated by `/`, where `.` matches any letter. It can be rotated
+    /// and flipped.
+    fn pattern(pattern: &str) -> Shape {
+        let mut cells = Vec::new();
+        for (r, row) in pattern.split('/').enumerate() {
+            for (c, b) in row.bytes().enumerate() {
+                if b != b'.' {
+                    cells.push((r as i32, c as i32, b));
+                }
+            }
+        }
+
+        let mut orientations = Vec::new();
+        for (i, name) in SYMMETRIES.iter().enumerate() {
+            let mut m = if i < 4 {
+                [[1, 0], [0, 1]]
+            } else {
+                [[1, 0], [0, -1]]
+            };
+            for _ in 0..(i % 4) {
+                // (r, c) -> (c, -r)
+                m = [[m[1][0], m[1][1]], [-m[0][0], -m[0][1]]];
+            }
+            orientations.push((*name, m));
+        }
+
+        Shape::new(pattern.into(), cells, orientations)
+    }
+
+    /// Drops orientations that need the same letters in the same places as an earlier one, such
+    /// as a palindrome read backwards, so nothing is counted twice.
+    fn new(
+        name: String,
+        cells: Vec<(i32, i32, u8)>,
+        orientations: Vec<(&'static str, [[i32; 2]; 2])>,
+    ) -> Shape {
+        let mut shape = Shape {
+            name,
+            cells,
+            orientations: Vec::new(),
+        };
+
+        let mut seen = Vec::new();
+        for (name, m) in orientations {
+            let mut placed = shape.place(&m);
+            let min_r = placed.iter().map(|p| p.0).min().unwrap_or(0);
+            let min_c = placed.iter().map(|p| p.1).min().unwrap_or(0);
+            for p in &mut placed {
+                p.0 -= min_r;
+                p.1 -= min_c;
+            }
+            placed.sort();
+
+            if !seen.contains(&placed) {
+                seen.push(placed);
+                shape.orientations.push((name, m));
+            }
+        }
+
+        shape
+    }
+
+    fn place(&self, m: &[[i32; 2]; 2]) -> Vec<(i32, i32, u8)> {
+        self.cells
+            .iter()
+            .map(|&(r, c, b)| (m[0][0] * r + m[0][1] * c, m[1][0] * r + m[1][1] * c, b))
+            .collect()
+    }
+
+    /// How far the shape can reach from where it starts, in any direction.
+    fn reach(&self) -> usize {
+        self.cells
+            .iter()
+            .map(|&(r, c, _)| r.max(c) as usize)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+struct Match {
+    shape: usize,
+    orientation: &'static str,
+    row: usize,
+    col: usize,
+    cells: Vec<(usize, usize)>,
+}
+
+pub fn run(words: &[String], patterns: &[String], list_matches: bool, highlight: Option<&str>) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 4).unwrap();
-    let contents = contents.iter().map(|s| s.as_bytes()).collect();
-    let contents = pad(&contents, PADDING, b' ');
+    let contents: Vec<&[u8]> = contents.iter().map(|s| s.as_bytes()).collect();
 
     part1(&mut writer, &contents);
     part2(&mut writer, &contents);
-}
 
-fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &[Vec<u8>]) {
-    // horizontal
-    let mut total = count_part1(contents, 1, 0);
-    total += count_part1(contents, -1, 0);
+    let shapes: Vec<Shape> = words
+        .iter()
+        .map(|w| Shape::word(w))
+        .chain(patterns.iter().map(|p| Shape::pattern(p)))
+        .collect();
+    if shapes.is_empty() {
+        return;
+    }
 
-    // vertical
-    total += count_part1(contents, 0, 1);
-    total += count_part1(contents, 0, -1);
+    let matches = search(&contents, &shapes);
+    if list_matches {
+        for m in &matches {
+            println!(
+                "{} at row {}, column {} ({})",
+                shapes[m.shape].name, m.row, m.col, m.orientation
+            );
+        }
+    }
+    for (i, shape) in shapes.iter().enumerate() {
+        println!(
+            "{}: {} matches",
+            shape.name,
+            matches.iter().filter(|m| m.shape == i).count()
+        );
+    }
 
-    // diagonal
-    total += count_part1(contents, 1, 1);
-    total += count_part1(contents, 1, -1);
-    total += count_part1(contents, -1, 1);
-    total += count_part1(contents, -1, -1);
+    if let Some(mode) = highlight {
+        print_highlighted(&contents, &matches, mode);
+    }
+}
 
+fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &Vec<&[u8]>) {
+    let total = search(contents, &[Shape::word("XMAS")]).len();
     printwriteln!(writer, "part 1: {}", total).unwrap();
 }
 
-fn part2<W: Write>(writer: &mut BufWriter<W>, contents: &[Vec<u8>]) {
-    let mut total = 0;
-    for i in PADDING..(contents.len() - PADDING) {
-        for j in PADDING..(contents[i].len() - PADDING) {
-            let mas1 = (contents[i][j] == b'M'
-                && contents[i + 1][j + 1] == b'A'
-                && contents[i + 2][j + 2] == b'S')
-                || (contents[i][j] == b'S'
-                    && contents[i + 1][j + 1] == b'A'
-                    && contents[i + 2][j + 2] == b'M');
-            let mas2 = (contents[i][j + 2] == b'M'
-                && contents[i + 1][j + 1] == b'A'
-                && contents[i + 2][j] == b'S')
-                || (contents[i][j + 2] == b'S'
-                    && contents[i + 1][j + 1] == b'A'
-                    && contents[i + 2][j] == b'M');
-            if mas1 && mas2 {
-                total += 1;
+fn part2<W: Write>(writer: &mut BufWriter<W>, contents: &Vec<&[u8]>) {
+    let total = search(contents, &[Shape::pattern("M.S/.A./M.S")]).len();
+    printwriteln!(writer, "part 2: {}", total).unwrap();
+}
+
+/// Finds every place each shape fits, in every orientation. The grid is padded by as far as
+/// any shape can reach so that nothing needs bounds checks.
+fn search(contents: &Vec<&[u8]>, shapes: &[Shape]) -> Vec<Match> {
+    let padding = shapes.iter().map(Shape::reach).max().unwrap_or(0);
+    let contents = pad(contents, padding, b' ');
+
+    let mut matches = Vec::new();
+    for (s, shape) in shapes.iter().enumerate() {
+        for (orientation, m) in &shape.orientations {
+            let placed = shape.place(m);
+            for i in padding..(contents.len() - padding) {
+                for j in padding..(contents[i].len() - padding) {
+                    if placed
+                        .iter()
+                        .all(|&(r, c, b)| contents[usize_plus_i(i, r)][usize_plus_i(j, c)] == b)
+                    {
+                        matches.push(Match {
+                            shape: s,
+                            orientation,
+                            row: i - padding,
+                            col: j - padding,
+                            cells: placed
+                                .iter()
+                                .map(|&(r, c, _)| {
+                                    (usize_plus_i(i, r) - padding, usize_plus_i(j, c) - padding)
+                                })
+                                .collect(),
+                        });
+                    }
+                }
             }
         }
     }
 
-    printwriteln!(writer, "part 2: {}", total).unwrap();
+    matches
 }
 
-fn count_part1(contents: &[Vec<u8>], horiz: i32, vert: i32) -> u32 {
-    let mut total = 0;
-    for i in PADDING..(contents.len() - PADDING) {
-        for j in PADDING..(contents[i].len() - PADDING) {
-            if contents[i][j] != b'X' {
-                continue;
-            }
-            if contents[usize_plus_i(i, horiz)][usize_plus_i(j, vert)] != b'M' {
-                continue;
-            }
-            if contents[usize_plus_i(i, 2 * horiz)][usize_plus_i(j, 2 * vert)] != b'A' {
-                continue;
-            }
-            if contents[usize_plus_i(i, 3 * horiz)][usize_plus_i(j, 3 * vert)] != b'S' {
-                continue;
-            }
-            total += 1
+/// Shows where the matches are, as in the puzzle: "dots" replaces every letter that isn't part
+/// of a match with `.`, "colour" colours the ones that are, and "heatmap" shows how many matches
+/// cover each letter (`*` for 10 or more).
+fn print_highlighted(contents: &[&[u8]], matches: &[Match], mode: &str) {
+    let mut coverage = vec![vec![0; contents[0].len()]; contents.len()];
+    for m in matches {
+        for &(r, c) in &m.cells {
+            coverage[r][c] += 1;
         }
     }
 
-    total
+    for (row, covered) in contents.iter().zip(&coverage) {
+        for (&b, &n) in row.iter().zip(covered) {
+            match (mode, n) {
+                ("dots", 0) | ("heatmap", 0) => print!("."),
+                ("dots", _) | ("colour", 0) => print!("{}", b as char),
+                ("colour", _) => print!("{}{}{}", GREEN, b as char, RESET),
+                ("heatmap", 1..=9) => print!("{}", n),
+                ("heatmap", _) => print!("*"),
+                _ => panic!(
+                    "unknown highlight mode {}, expected dots, colour or heatmap",
+                    mode
+                ),
+            }
+        }
+        println!();
+    }
 }
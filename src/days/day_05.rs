@@ -1,11 +1,161 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     io::{BufWriter, Write},
 };
 
 use aoclib_rs::{prep_io, printwriteln, split_and_parse};
 
-pub fn run() {
+/// A rule `before|after` that an update breaks by printing `after` first.
+struct Violation {
+    before: i32,
+    after: i32,
+    before_index: usize,
+    after_index: usize,
+}
+
+/// Take a page out of the update and put it back in somewhere else.
+enum Move {
+    Front(i32),
+    After(i32, i32),
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Move::Front(page) => write!(f, "move {} to the front", page),
+            Move::After(page, prev) => write!(f, "move {} to after {}", page, prev),
+        }
+    }
+}
+
+struct Report {
+    update: Vec<i32>,
+    violations: Vec<Violation>,
+    /// As few moves as possible that fix the order, made in this order, or None if the rules
+    /// between the pages have a cycle.
+    moves: Option<Vec<Move>>,
+}
+
+impl Report {
+    /// Checks every pair of pages in the update against the rules between them, directly or
+    /// through other pages in the update.
+    fn new(rules: &HashMap<i32, Vec<i32>>, update: &[i32]) -> Report {
+        let n = update.len();
+        let index: HashMap<i32, usize> = update.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+
+        // must[a][b] means the page at a has to be printed before the page at b
+        let mut must = vec![vec![false; n]; n];
+        for (b, page) in update.iter().enumerate() {
+            for dep in rules.get(page).into_iter().flatten() {
+                if let Some(&a) = index.get(dep) {
+                    must[a][b] = true;
+                }
+            }
+        }
+
+        let mut violations = Vec::new();
+        for b in 0..n {
+            for a in (b + 1)..n {
+                if must[a][b] {
+                    violations.push(Violation {
+                        before: update[a],
+                        after: update[b],
+                        before_index: a,
+                        after_index: b,
+                    });
+                }
+            }
+        }
+
+        for k in 0..n {
+            let through = must[k].clone();
+            for row in must.iter_mut() {
+                if row[k] {
+                    for (m, t) in row.iter_mut().zip(&through) {
+                        *m |= *t;
+                    }
+                }
+            }
+        }
+
+        let moves = if (0..n).any(|i| must[i][i]) {
+            None
+        } else {
+            Some(fewest_moves(update, &must))
+        };
+
+        Report {
+            update: update.to_owned(),
+            violations,
+            moves,
+        }
+    }
+
+    fn print_table(&self) {
+        println!("update {:?}", self.update);
+        if self.violations.is_empty() {
+            println!("  no rules broken");
+            return;
+        }
+
+        println!("  {:>7} | {:>6} | {:>5}", "rule", "before", "after");
+        for v in &self.violations {
+            println!(
+                "  {:>7} | {:>6} | {:>5}",
+                format!("{}|{}", v.before, v.after),
+                v.before_index,
+                v.after_index
+            );
+        }
+
+        match &self.moves {
+            None => println!("  no valid order: the rules between these pages have a cycle"),
+            Some(moves) => {
+                for m in moves {
+                    println!("  {}", m);
+                }
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let violations: Vec<String> = self
+            .violations
+            .iter()
+            .map(|v| {
+                format!(
+                    r#"{{"rule":"{}|{}","before_index":{},"after_index":{}}}"#,
+                    v.before, v.after, v.before_index, v.after_index
+                )
+            })
+            .collect();
+        let moves = match &self.moves {
+            None => "null".into(),
+            Some(moves) => {
+                let moves: Vec<String> = moves
+                    .iter()
+                    .map(|m| match m {
+                        Move::Front(page) => format!(r#"{{"page":{},"after":null}}"#, page),
+                        Move::After(page, prev) => {
+                            format!(r#"{{"page":{},"after":{}}}"#, page, prev)
+                        }
+                    })
+                    .collect();
+                format!("[{}]", moves.join(","))
+            }
+        };
+
+        format!(
+            r#"{{"update":{:?},"violations":[{}],"moves":{}}}"#,
+            self.update,
+            violations.join(","),
+            moves
+        )
+    }
+}
+
+pub fn run(violations: Option<&str>) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 5).unwrap();
 
@@ -34,9 +184,28 @@ pub fn run() {
     println!("rules: {:?}", rules);
     println!("order: {:?}", order);
 
-    let mut incorrect_order = part1(&mut writer, &mut rules, &order);
+    let incorrect_order = part1(&mut writer, &mut rules, &order);
     println!("incorrectly ordered: {:?}", incorrect_order);
-    part2(&mut writer, &rules, &mut incorrect_order);
+    part2(&mut writer, &rules, &incorrect_order);
+
+    if let Some(format) = violations {
+        let reports: Vec<Report> = incorrect_order
+            .iter()
+            .map(|update| Report::new(&rules, update))
+            .collect();
+        match format {
+            "table" => reports.iter().for_each(Report::print_table),
+            "json" => println!(
+                "[{}]",
+                reports
+                    .iter()
+                    .map(Report::to_json)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            _ => panic!("unknown report format {}, expected table or json", format),
+        }
+    }
 }
 
 fn part1<W: Write>(
@@ -78,60 +247,265 @@ fn part1<W: Write>(
     incorrect_updates
 }
 
-fn part2<W: Write>(
-    writer: &mut BufWriter<W>,
-    rules: &HashMap<i32, Vec<i32>>,
-    order: &mut Vec<Vec<i32>>,
-) {
+fn part2<W: Write>(writer: &mut BufWriter<W>, rules: &HashMap<i32, Vec<i32>>, order: &[Vec<i32>]) {
     let mut total = 0;
     for update in order {
         println!("update {:?}", update);
-        let mut new_update = Vec::new();
-        let mut printed = HashMap::new();
-        while !update.is_empty() {
-            fill_in_next(rules, update, &mut new_update, &mut printed);
-        }
+        match topological_sort(rules, update) {
+            Ok((new_update, unique)) => {
+                println!(
+                    "new_update: {:?} ({})",
+                    new_update,
+                    if unique {
+                        "the only valid order"
+                    } else {
+                        "one of several valid orders"
+                    }
+                );
 
-        println!("new_update: {:?}", new_update);
-
-        total += new_update[new_update.len() / 2];
+                total += new_update[new_update.len() / 2];
+            }
+            Err(cycle) => {
+                let rules: Vec<String> = cycle
+                    .iter()
+                    .zip(cycle.iter().cycle().skip(1))
+                    .map(|(before, after)| format!("{}|{}", before, after))
+                    .collect();
+                println!(
+                    "no valid order for {:?}: the rules {} form a cycle",
+                    update,
+                    rules.join(", ")
+                );
+            }
+        }
     }
 
     printwriteln!(writer, "part 2: {}", total).unwrap();
 }
 
-fn fill_in_next(
+/// Orders an update's pages with Kahn's algorithm, using only the rules between pages in the
+/// update. Returns the new order and whether it's the only valid one, or the pages of a cycle in
+/// the rules, in order, if there's no valid order at all.
+fn topological_sort(
     rules: &HashMap<i32, Vec<i32>>,
-    update: &mut Vec<i32>,
-    new_update: &mut Vec<i32>,
-    printed: &mut HashMap<i32, bool>,
-) {
-    for i in 0..update.len() {
-        if deps_are_satisfied(rules, update[i], printed, update) {
-            println!("deps are satisfied for {}", update[i]);
-            new_update.push(update[i]);
-            printed.insert(update[i], true);
-            update.remove(i);
-            break;
+    update: &[i32],
+) -> Result<(Vec<i32>, bool), Vec<i32>> {
+    let pages: HashSet<i32> = update.iter().cloned().collect();
+    let deps = |page: &i32| -> Vec<i32> {
+        rules.get(page).map_or(Vec::new(), |deps| {
+            deps.iter().filter(|d| pages.contains(d)).cloned().collect()
+        })
+    };
+
+    let mut in_degree = HashMap::new();
+    let mut successors: HashMap<i32, Vec<i32>> = HashMap::new();
+    for page in update {
+        let page_deps = deps(page);
+        in_degree.insert(*page, page_deps.len());
+        for dep in page_deps {
+            successors.entry(dep).or_default().push(*page);
+        }
+    }
+
+    let mut ready: VecDeque<i32> = update
+        .iter()
+        .filter(|page| in_degree[page] == 0)
+        .cloned()
+        .collect();
+    let mut sorted = Vec::with_capacity(update.len());
+    let mut unique = true;
+    while let Some(page) = ready.pop_front() {
+        // any of the other ready pages could have gone here instead
+        if !ready.is_empty() {
+            unique = false;
+        }
+
+        sorted.push(page);
+        for next in successors.get(&page).into_iter().flatten() {
+            let d = in_degree.get_mut(next).unwrap();
+            *d -= 1;
+            if *d == 0 {
+                ready.push_back(*next);
+            }
         }
     }
+
+    if sorted.len() == update.len() {
+        return Ok((sorted, unique));
+    }
+
+    // every page left over has a dep that's also left over, so following deps from any of them
+    // has to come back round
+    let mut path = vec![*update.iter().find(|page| in_degree[page] > 0).unwrap()];
+    loop {
+        let page = *path.last().unwrap();
+        let dep = deps(&page)
+            .into_iter()
+            .find(|dep| in_degree[dep] > 0)
+            .unwrap();
+        if let Some(i) = path.iter().position(|p| *p == dep) {
+            let mut cycle = path.split_off(i);
+            cycle.reverse();
+            return Err(cycle);
+        }
+        path.push(dep);
+    }
 }
 
-fn deps_are_satisfied(
-    rules: &HashMap<i32, Vec<i32>>,
-    i: i32,
-    printed: &mut HashMap<i32, bool>,
-    update: &[i32],
+/// The pages that stay put have to be in an order the rules allow, so they can't include both
+/// pages of any pair that's the wrong way round. Those pairs form a partial order on positions,
+/// so the most pages that can stay is its largest antichain, found from a maximum matching by
+/// Dilworth's and Konig's theorems. The rest are moved in the order they end up in.
+fn fewest_moves(update: &[i32], must: &[Vec<bool>]) -> Vec<Move> {
+    let n = update.len();
+    // wrong_way[i] are the later positions j whose page has to come before the page at i
+    let wrong_way: Vec<Vec<usize>> = (0..n)
+        .map(|i| ((i + 1)..n).filter(|&j| must[j][i]).collect())
+        .collect();
+
+    let mut matched: Vec<Option<usize>> = vec![None; n];
+    for i in 0..n {
+        augment(i, &wrong_way, &mut vec![false; n], &mut matched);
+    }
+
+    // positions reachable by alternating paths from the unmatched left side
+    let mut left_reached = vec![false; n];
+    let mut right_reached = vec![false; n];
+    let mut stack: Vec<usize> = (0..n).filter(|i| !matched.contains(&Some(*i))).collect();
+    for &i in &stack {
+        left_reached[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        for &j in &wrong_way[i] {
+            if !right_reached[j] {
+                right_reached[j] = true;
+                if let Some(k) = matched[j] {
+                    if !left_reached[k] {
+                        left_reached[k] = true;
+                        stack.push(k);
+                    }
+                }
+            }
+        }
+    }
+    let stays: Vec<bool> = (0..n)
+        .map(|i| left_reached[i] && !right_reached[i])
+        .collect();
+    debug_assert_eq!(
+        stays.iter().filter(|s| **s).count(),
+        n - matched.iter().flatten().count()
+    );
+
+    // a valid order that keeps the pages that stay in the same order as they are now
+    let mut placed = vec![false; n];
+    let mut new_order = Vec::with_capacity(n);
+    while new_order.len() < n {
+        let next = (0..n)
+            .find(|&b| {
+                !placed[b]
+                    && (0..n).all(|a| {
+                        placed[a] || a == b || !(must[a][b] || (stays[a] && stays[b] && a < b))
+                    })
+            })
+            .unwrap();
+        placed[next] = true;
+        new_order.push(next);
+    }
+
+    new_order
+        .iter()
+        .enumerate()
+        .filter(|(_, &i)| !stays[i])
+        .map(|(k, &i)| match k {
+            0 => Move::Front(update[i]),
+            _ => Move::After(update[i], update[new_order[k - 1]]),
+        })
+        .collect()
+}
+
+/// Looks for an augmenting path from left vertex i (Kuhn's algorithm).
+fn augment(
+    i: usize,
+    edges: &[Vec<usize>],
+    seen: &mut [bool],
+    matched: &mut [Option<usize>],
 ) -> bool {
-    for dep in rules.get(&i).unwrap() {
-        let dep_printed = printed.entry(*dep).or_insert(false);
-        if !update.contains(dep) {
+    for &j in &edges[i] {
+        if seen[j] {
             continue;
         }
-        if !*dep_printed {
-            return false;
+        seen[j] = true;
+
+        let free = match matched[j] {
+            Some(k) => augment(k, edges, seen, matched),
+            None => true,
+        };
+        if free {
+            matched[j] = Some(i);
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_RULES: &str =
+        "47|53 97|13 97|61 97|47 75|29 61|13 75|53 29|13 97|29 53|29 61|53 \
+                                 97|53 61|29 47|13 75|47 97|75 47|61 75|61 47|29 75|13 53|13";
+
+    fn parse_rules(rules: &str) -> HashMap<i32, Vec<i32>> {
+        let mut parsed: HashMap<i32, Vec<i32>> = HashMap::new();
+        for rule in rules.split_whitespace() {
+            let lsp = split_and_parse(rule, "|").unwrap();
+            parsed.entry(lsp[1]).or_default().push(lsp[0]);
         }
+
+        parsed
+    }
+
+    #[test]
+    fn example_orders() {
+        let rules = parse_rules(EXAMPLE_RULES);
+        assert_eq!(
+            topological_sort(&rules, &[75, 97, 47, 61, 53]),
+            Ok((vec![97, 75, 47, 61, 53], true))
+        );
+        assert_eq!(
+            topological_sort(&rules, &[61, 13, 29]),
+            Ok((vec![61, 29, 13], true))
+        );
+        assert_eq!(
+            topological_sort(&rules, &[97, 13, 75, 29, 47]),
+            Ok((vec![97, 75, 47, 29, 13], true))
+        );
+    }
+
+    #[test]
+    fn several_valid_orders() {
+        let rules = parse_rules("1|2");
+        assert_eq!(
+            topological_sort(&rules, &[3, 2, 1]),
+            Ok((vec![3, 1, 2], false))
+        );
     }
 
-    true
+    #[test]
+    fn cycle_is_reported_in_order() {
+        // 4 can't be placed either, but isn't part of the cycle
+        let rules = parse_rules("1|2 2|3 3|1 3|4");
+        let Err(cycle) = topological_sort(&rules, &[4, 1, 2, 3]) else {
+            panic!("expected a cycle");
+        };
+
+        // each page has to come before the next one, going round
+        let n = cycle.len();
+        assert_eq!(n, 3);
+        for i in 0..n {
+            assert!(rules[&cycle[(i + 1) % n]].contains(&cycle[i]));
+        }
+    }
 }
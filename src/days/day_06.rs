@@ -1,6 +1,7 @@
 use std::{
-    collections::HashSet,
+    collections::HashMap,
     io::{BufWriter, Write},
+    thread,
 };
 
 use aoclib_rs::{
@@ -8,6 +9,13 @@ use aoclib_rs::{
     prep_io, printwriteln, u8_to_string,
 };
 
+// marks a guard walking off the map in the jump table
+const EXIT: u32 = u32::MAX;
+
+// which ways the guard has walked through a cell, for drawing its route
+const VERTICAL: u8 = 1;
+const HORIZONTAL: u8 = 2;
+
 #[derive(Copy, Clone)]
 struct Position {
     c: u8,
@@ -20,7 +28,144 @@ impl Position {
     }
 }
 
-pub fn run() {
+/// For every cell and heading, the cell the guard stops on in front of the next obstacle.
+struct Jumps {
+    width: usize,
+    height: usize,
+    stops: Vec<u32>,
+}
+
+impl Jumps {
+    fn new(contents: &[Vec<Position>]) -> Jumps {
+        let height = contents.len();
+        let width = contents[0].len();
+        let mut stops = vec![EXIT; width * height * 4];
+
+        for dir in [Dir4::Up, Dir4::Right, Dir4::Down, Dir4::Left] {
+            let (dx, dy) = delta(dir);
+            // fill in each cell after the one it walks into
+            let ys: Vec<usize> = if dy > 0 {
+                (0..height).rev().collect()
+            } else {
+                (0..height).collect()
+            };
+            let xs: Vec<usize> = if dx > 0 {
+                (0..width).rev().collect()
+            } else {
+                (0..width).collect()
+            };
+
+            for &y in &ys {
+                for &x in &xs {
+                    let cell = y * width + x;
+                    stops[state(cell, dir)] = match step(width, height, (x, y), dir) {
+                        None => EXIT,
+                        Some((nx, ny)) if contents[ny][nx].c == b'#' => cell as u32,
+                        Some((nx, ny)) => stops[state(ny * width + nx, dir)],
+                    };
+                }
+            }
+        }
+
+        Jumps {
+            width,
+            height,
+            stops,
+        }
+    }
+
+    /// Where a guard heading `dir` from `cell` stops, taking the extra `obstruction` into account
+    /// without changing the table, or None if it walks off the map.
+    fn next_stop(&self, cell: usize, dir: Dir4, obstruction: usize) -> Option<usize> {
+        let (x, y) = (cell % self.width, cell / self.width);
+        let (ox, oy) = (obstruction % self.width, obstruction / self.width);
+        let stop = self.stops[state(cell, dir)];
+
+        let ahead = match dir {
+            Dir4::Up => (ox == x && oy < y).then(|| y - oy),
+            Dir4::Right => (oy == y && ox > x).then(|| ox - x),
+            Dir4::Down => (ox == x && oy > y).then(|| oy - y),
+            Dir4::Left => (oy == y && ox < x).then(|| x - ox),
+        };
+        let stop_distance = if stop == EXIT {
+            usize::MAX
+        } else {
+            let stop = stop as usize;
+            (stop % self.width).abs_diff(x) + (stop / self.width).abs_diff(y)
+        };
+
+        match ahead {
+            Some(a) if a <= stop_distance => {
+                let (dx, dy) = delta(dir);
+                let steps = a as isize - 1;
+                let nx = x.checked_add_signed(dx * steps).unwrap();
+                let ny = y.checked_add_signed(dy * steps).unwrap();
+                Some(ny * self.width + nx)
+            }
+            _ => (stop != EXIT).then_some(stop as usize),
+        }
+    }
+
+    /// Whether the guard, heading `dir` from `cell`, ends up going round in circles once
+    /// `obstruction` is added.
+    fn loops(
+        &self,
+        mut cell: usize,
+        mut dir: Dir4,
+        obstruction: usize,
+        seen: &mut StateSet,
+    ) -> bool {
+        seen.clear();
+        while let Some(stop) = self.next_stop(cell, dir, obstruction) {
+            cell = stop;
+            dir = dir.rotate_right();
+            if !seen.insert(state(cell, dir)) {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// A dense bitmap of (cell, heading) states, which remembers which words it has set so that
+/// clearing it between walks is cheap.
+struct StateSet {
+    bits: Vec<u64>,
+    touched: Vec<usize>,
+}
+
+impl StateSet {
+    fn new(states: usize) -> StateSet {
+        StateSet {
+            bits: vec![0; states.div_ceil(64)],
+            touched: Vec::new(),
+        }
+    }
+
+    /// Returns false if the state was already there.
+    fn insert(&mut self, s: usize) -> bool {
+        let (word, bit) = (s / 64, 1 << (s % 64));
+        if self.bits[word] & bit != 0 {
+            return false;
+        }
+
+        if self.bits[word] == 0 {
+            self.touched.push(word);
+        }
+        self.bits[word] |= bit;
+
+        true
+    }
+
+    fn clear(&mut self) {
+        for word in self.touched.drain(..) {
+            self.bits[word] = 0;
+        }
+    }
+}
+
+pub fn run(show_loop: &[usize]) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 6).unwrap();
     let contents: Vec<Vec<Position>> = contents
@@ -28,16 +173,22 @@ pub fn run() {
         .map(|s| s.as_bytes().iter().map(|b| Position::new(*b)).collect())
         .collect();
 
-    let part1_contents = part1(&mut writer, contents.clone());
-    part2(&mut writer, &contents, &part1_contents);
+    let first_visits = part1(&mut writer, contents.clone());
+    part2(&mut writer, &contents, &first_visits);
+
+    match *show_loop {
+        [] => {}
+        [x, y] => print_loop(&contents, (x, y)),
+        _ => panic!("expected the obstruction as x,y"),
+    }
 }
 
 fn part1<W: Write>(
     writer: &mut BufWriter<W>,
     mut contents: Vec<Vec<Position>>,
-) -> Vec<Vec<Position>> {
+) -> Vec<((usize, usize), Dir4)> {
     let start = find_start(&contents);
-    check_for_loop(&mut contents, start);
+    let first_visits = patrol(&mut contents, start);
 
     for row in &mut *contents {
         for cell in row {
@@ -68,50 +219,127 @@ fn part1<W: Write>(
     )
     .unwrap();
 
-    contents
+    first_visits
 }
 
+/// Only cells on the original patrol can change it. The guard's route is the same up until it
+/// first reaches the obstruction, so each candidate is tried from just before that point.
 fn part2<W: Write>(
     writer: &mut BufWriter<W>,
     contents: &[Vec<Position>],
-    part1_contents: &[Vec<Position>],
+    first_visits: &[((usize, usize), Dir4)],
 ) {
+    let jumps = Jumps::new(contents);
+    let width = jumps.width;
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = first_visits.len().div_ceil(threads).max(1);
+    let mut obstructions: Vec<(usize, usize)> = thread::scope(|scope| {
+        let handles: Vec<_> = first_visits
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let jumps = &jumps;
+                scope.spawn(move || {
+                    let mut seen = StateSet::new(jumps.width * jumps.height * 4);
+                    chunk
+                        .iter()
+                        .filter_map(|&((x, y), dir)| {
+                            let (ox, oy) = step(jumps.width, jumps.height, (x, y), dir).unwrap();
+                            jumps
+                                .loops(y * width + x, dir, oy * width + ox, &mut seen)
+                                .then_some((ox, oy))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+
+    obstructions.sort_by_key(|&(x, y)| (y, x));
+    for (x, y) in &obstructions {
+        println!("obstruction at {},{} makes a loop", x, y);
+    }
+
+    printwriteln!(writer, "part 2: {}", obstructions.len()).unwrap();
+}
+
+/// Draws the guard's route once `obstruction` is added, as in the puzzle, and says when the guard
+/// gets stuck in a loop and how long it is.
+fn print_loop(contents: &[Vec<Position>], obstruction: (usize, usize)) {
+    let (height, width) = (contents.len(), contents[0].len());
     let start = find_start(contents);
+    assert_ne!(start, obstruction, "the guard is standing there");
+    assert_ne!(
+        contents[obstruction.1][obstruction.0].c, b'#',
+        "there is already an obstacle there"
+    );
 
-    let mut potential_locations = Vec::new();
-    for (y, row) in part1_contents.iter().enumerate() {
-        for (x, cell) in row.iter().enumerate() {
-            if cell.visited && (x as i32 != start.0 || y as i32 != start.1) {
-                potential_locations.push((x, y));
-            }
+    let mut axes = vec![vec![0; width]; height];
+    let mut seen = HashMap::new();
+    let (mut x, mut y) = start;
+    let mut dir = Dir4::Up;
+    let mut steps = 0;
+    let cycle = loop {
+        let Some((nx, ny)) = step(width, height, (x, y), dir) else {
+            break None;
+        };
+        if contents[ny][nx].c == b'#' || (nx, ny) == obstruction {
+            dir = dir.rotate_right();
+            continue;
         }
-    }
 
-    let mut total = 0;
-    for (i, pos) in potential_locations.iter().enumerate() {
-        let mut contents = contents.to_owned();
-        contents[pos.1][pos.0].c = b'#';
+        let axis = match dir {
+            Dir4::Up | Dir4::Down => VERTICAL,
+            Dir4::Left | Dir4::Right => HORIZONTAL,
+        };
+        axes[y][x] |= axis;
+        axes[ny][nx] |= axis;
 
-        if check_for_loop(&mut contents, start) {
-            total += 1;
+        (x, y) = (nx, ny);
+        steps += 1;
+        if let Some(entry) = seen.insert(((x, y), dir), steps) {
+            break Some((entry, steps - entry));
         }
+    };
 
-        println!(
-            "done another loop ({}/{}) - total so far {}",
-            i,
-            potential_locations.len(),
-            total
-        );
+    for (y, row) in contents.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let c = if (x, y) == obstruction {
+                'O'
+            } else if cell.c == b'^' {
+                '^'
+            } else {
+                match axes[y][x] {
+                    VERTICAL => '|',
+                    HORIZONTAL => '-',
+                    0 => cell.c as char,
+                    _ => '+',
+                }
+            };
+            print!("{}", c);
+        }
+        println!();
     }
 
-    printwriteln!(writer, "part 2: {}", total).unwrap();
+    match cycle {
+        Some((entry, length)) => println!(
+            "the guard enters the loop at step {} and goes round it every {} steps",
+            entry, length
+        ),
+        None => println!("no loop: the guard leaves the map after {} steps", steps),
+    }
 }
 
-fn find_start(contents: &[Vec<Position>]) -> (i32, i32) {
+fn find_start(contents: &[Vec<Position>]) -> (usize, usize) {
     for (y, row) in contents.iter().enumerate() {
         for (x, cell) in row.iter().enumerate() {
             if cell.c == b'^' {
-                return (x as i32, y as i32);
+                return (x, y);
             }
         }
     }
@@ -119,65 +347,51 @@ fn find_start(contents: &[Vec<Position>]) -> (i32, i32) {
     panic!("^ not found");
 }
 
-fn check_for_loop(contents: &mut [Vec<Position>], start: (i32, i32)) -> bool {
+/// Walks the guard off the map one cell at a time, marking the cells it visits. Returns, for
+/// each cell other than the start in the order they're first visited, where the guard was and
+/// which way it was heading just before stepping onto it.
+fn patrol(contents: &mut [Vec<Position>], start: (usize, usize)) -> Vec<((usize, usize), Dir4)> {
+    let (height, width) = (contents.len(), contents[0].len());
     let (mut x, mut y) = start;
     let mut dir = Dir4::Up;
+    contents[y][x].visited = true;
 
-    contents[y as usize][x as usize].visited = true;
-    let mut hs = HashSet::new();
-    hs.insert((start, dir));
-
-    'outer: loop {
-        let (mut next, mut bk);
-        (next, x, y, bk) = check_delta(dir, x, y, contents);
-        if bk {
-            break 'outer;
-        }
-
-        let mut count = 1;
-        while contents[y as usize][x as usize].c == b'#' {
-            x -= next.0;
-            y -= next.1;
-
+    let mut first_visits = Vec::new();
+    while let Some((nx, ny)) = step(width, height, (x, y), dir) {
+        if contents[ny][nx].c == b'#' {
             dir = dir.rotate_right();
-
-            (next, x, y, bk) = check_delta(dir, x, y, contents);
-            if bk {
-                break 'outer;
-            }
-
-            count += 1;
-
-            if count > 4 {
-                panic!("trapped??");
-            }
+            continue;
         }
 
-        if hs.contains(&((x, y), dir)) {
-            return true;
+        if !contents[ny][nx].visited {
+            contents[ny][nx].visited = true;
+            first_visits.push(((x, y), dir));
         }
-
-        contents[y as usize][x as usize].visited = true;
-        hs.insert(((x, y), dir));
+        (x, y) = (nx, ny);
     }
 
-    false
+    first_visits
 }
 
-fn check_delta(
-    dir: Dir4,
-    mut x: i32,
-    mut y: i32,
-    contents: &[Vec<Position>],
-) -> ((i32, i32), i32, i32, bool) {
-    let (nx, ny) = dir.delta();
-    let next = (nx as i32, ny as i32);
-    x += next.0;
-    y += next.1;
-
-    if y < 0 || y >= contents.len() as i32 || x < 0 || x >= contents[0].len() as i32 {
-        return (next, x, y, true);
-    }
+fn step(width: usize, height: usize, (x, y): (usize, usize), dir: Dir4) -> Option<(usize, usize)> {
+    let (dx, dy) = delta(dir);
+    let nx = x.checked_add_signed(dx)?;
+    let ny = y.checked_add_signed(dy)?;
 
-    (next, x, y, false)
+    (nx < width && ny < height).then_some((nx, ny))
+}
+
+fn delta(dir: Dir4) -> (isize, isize) {
+    let (dx, dy) = dir.delta();
+    (dx as isize, dy as isize)
+}
+
+fn state(cell: usize, dir: Dir4) -> usize {
+    cell * 4
+        + match dir {
+            Dir4::Up => 0,
+            Dir4::Right => 1,
+            Dir4::Down => 2,
+            Dir4::Left => 3,
+        }
 }
@@ -2,7 +2,169 @@ use std::io::{BufWriter, Write};
 
 use aoclib_rs::{prep_io, printwriteln, split_and_parse};
 
-pub fn run() {
+/// What an operator's left operand must have been, given its right operand and result.
+enum Inverse {
+    Exact(u64),
+    Impossible,
+    /// More than one left operand could give the result, so it has to be searched for.
+    Unknown,
+}
+
+trait Operator {
+    fn symbol(&self) -> String;
+
+    /// None if the result isn't a u64, e.g. on overflow or a negative result.
+    fn apply(&self, left: u64, right: u64) -> Option<u64>;
+
+    fn unapply(&self, _result: u64, _right: u64) -> Inverse {
+        Inverse::Unknown
+    }
+}
+
+struct Add;
+
+impl Operator for Add {
+    fn symbol(&self) -> String {
+        "+".into()
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_add(right)
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Inverse {
+        result
+            .checked_sub(right)
+            .map_or(Inverse::Impossible, Inverse::Exact)
+    }
+}
+
+struct Sub;
+
+impl Operator for Sub {
+    fn symbol(&self) -> String {
+        "-".into()
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_sub(right)
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Inverse {
+        result
+            .checked_add(right)
+            .map_or(Inverse::Impossible, Inverse::Exact)
+    }
+}
+
+struct Mul;
+
+impl Operator for Mul {
+    fn symbol(&self) -> String {
+        "*".into()
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_mul(right)
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Inverse {
+        if right == 0 {
+            if result == 0 {
+                Inverse::Unknown
+            } else {
+                Inverse::Impossible
+            }
+        } else if result % right == 0 {
+            Inverse::Exact(result / right)
+        } else {
+            Inverse::Impossible
+        }
+    }
+}
+
+/// Integer division, rounding down.
+struct Div;
+
+impl Operator for Div {
+    fn symbol(&self) -> String {
+        "/".into()
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_div(right)
+    }
+}
+
+struct Pow;
+
+impl Operator for Pow {
+    fn symbol(&self) -> String {
+        "^".into()
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        match right {
+            0 => Some(1),
+            _ if left <= 1 => Some(left),
+            _ => left.checked_pow(u32::try_from(right).ok()?),
+        }
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Inverse {
+        match right {
+            0 if result == 1 => Inverse::Unknown,
+            0 => Inverse::Impossible,
+            _ if result <= 1 => Inverse::Exact(result),
+            // anything bigger than 1 overflows
+            64.. => Inverse::Impossible,
+            _ => {
+                let root = (result as f64).powf(1.0 / right as f64).round() as u64;
+                (root.saturating_sub(1)..=root + 1)
+                    .find(|a| a.checked_pow(right as u32) == Some(result))
+                    .map_or(Inverse::Impossible, Inverse::Exact)
+            }
+        }
+    }
+}
+
+/// Appends the digits of the right operand, written in the given base, to the left operand.
+struct Concat(u64);
+
+impl Concat {
+    /// What the left operand is multiplied by to make room for the right operand's digits.
+    fn shift(&self, right: u64) -> Option<u64> {
+        let mut shift = self.0;
+        while shift <= right {
+            shift = shift.checked_mul(self.0)?;
+        }
+
+        Some(shift)
+    }
+}
+
+impl Operator for Concat {
+    fn symbol(&self) -> String {
+        if self.0 == 10 {
+            "||".into()
+        } else {
+            format!("||{}", self.0)
+        }
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_mul(self.shift(right)?)?.checked_add(right)
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Inverse {
+        match self.shift(right) {
+            Some(shift) if result % shift == right => Inverse::Exact(result / shift),
+            _ => Inverse::Impossible,
+        }
+    }
+}
+
+pub fn run(operators: &[String]) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 7).unwrap();
     let contents: Vec<Vec<&str>> = contents.iter().map(|s| s.split(": ").collect()).collect();
@@ -17,26 +179,62 @@ pub fn run() {
 
     part1(&mut writer, &targets, &operands);
     part2(&mut writer, &targets, &operands);
+
+    if !operators.is_empty() {
+        let operators = parse_operators(operators);
+        let s = solve(&targets, &operands, &operators);
+        println!(
+            "operators {}: {}",
+            operators
+                .iter()
+                .map(|op| op.symbol())
+                .collect::<Vec<_>>()
+                .join(" "),
+            s
+        );
+    }
 }
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, targets: &[u64], operands: &[Vec<u64>]) {
-    let s = solve(targets, operands, false);
+    let s = solve(targets, operands, &parse_operators(&["*", "+"]));
     printwriteln!(writer, "part 1: {}", s).unwrap();
 }
 
 fn part2<W: Write>(writer: &mut BufWriter<W>, targets: &[u64], operands: &[Vec<u64>]) {
-    let s = solve(targets, operands, true);
+    let s = solve(targets, operands, &parse_operators(&["*", "+", "||"]));
     printwriteln!(writer, "part 2: {}", s).unwrap();
 }
 
-fn solve(targets: &[u64], operands: &[Vec<u64>], part2: bool) -> u64 {
+/// Parses operator symbols: `+`, `-`, `*`, `/`, `^`, `||` for decimal concatenation and `||k` for
+/// concatenation in base k.
+fn parse_operators<S: AsRef<str>>(symbols: &[S]) -> Vec<Box<dyn Operator>> {
+    symbols
+        .iter()
+        .map(|s| -> Box<dyn Operator> {
+            match s.as_ref() {
+                "+" => Box::new(Add),
+                "-" => Box::new(Sub),
+                "*" => Box::new(Mul),
+                "/" => Box::new(Div),
+                "^" => Box::new(Pow),
+                "||" => Box::new(Concat(10)),
+                s => match s.strip_prefix("||").map(|base| base.parse()) {
+                    Some(Ok(base)) if base >= 2 => Box::new(Concat(base)),
+                    _ => panic!("unknown operator {}", s),
+                },
+            }
+        })
+        .collect()
+}
+
+fn solve(targets: &[u64], operands: &[Vec<u64>], operators: &[Box<dyn Operator>]) -> u64 {
     let mut total = 0;
     for (i, ops) in operands.iter().enumerate() {
         let target = targets[i];
-        let mut operators = Vec::with_capacity(ops.len() - 1);
-        if permute_recursive(target, &ops[1..], ops[0], &mut operators, part2) == target {
+        let mut chosen = Vec::with_capacity(ops.len() - 1);
+        if solve_backward(target, ops, operators, &mut chosen) {
             total += target;
-            print_formula(target, ops, &mut operators);
+            print_formula(target, ops, &chosen);
         } else {
             println!("{} != {:?}", target, ops);
         }
@@ -45,60 +243,68 @@ fn solve(targets: &[u64], operands: &[Vec<u64>], part2: bool) -> u64 {
     total
 }
 
-fn permute_recursive(
+/// Looks for operators that make `ops`, evaluated left to right, come to `target`. Works back
+/// from the last operand, so each operator's inverse either pins down the value the rest must
+/// come to or rules the operator out. The chosen operators are pushed left to right.
+fn solve_backward<'a>(
     target: u64,
     ops: &[u64],
-    total: u64,
-    operators: &mut Vec<&str>,
-    part2: bool,
-) -> u64 {
-    if total > target {
-        return 0;
-    }
-
-    if ops.is_empty() {
-        return if total == target { total } else { 0 };
-    }
-
-    let t = permute_recursive(target, &ops[1..], total * ops[0], operators, part2);
-    if t == target {
-        //println!("{} * {} = {}", total, ops[0], total * ops[0]);
-        operators.push("*");
-        return t;
-    }
-    let t = permute_recursive(target, &ops[1..], total + ops[0], operators, part2);
-    if t == target {
-        //println!("{} + {} = {}", total, ops[0], total + ops[0]);
-        operators.push("+");
-        return t;
-    }
-
-    if part2 {
-        let total2 = (total.to_string() + ops[0].to_string().as_str())
-            .parse()
-            .unwrap();
-        let t = permute_recursive(target, &ops[1..], total2, operators, part2);
-        if t == target {
-            //println!("{} || {} = {}", total, ops[0], total2);
-            operators.push("||");
-            return t;
+    operators: &'a [Box<dyn Operator>],
+    chosen: &mut Vec<&'a dyn Operator>,
+) -> bool {
+    let (&last, rest) = ops.split_last().unwrap();
+    if rest.is_empty() {
+        return last == target;
+    }
+
+    for operator in operators {
+        let found = match operator.unapply(target, last) {
+            Inverse::Exact(left) => solve_backward(left, rest, operators, chosen),
+            Inverse::Impossible => false,
+            Inverse::Unknown => solve_forward(rest[0], &rest[1..], operators, chosen, &|total| {
+                operator.apply(total, last) == Some(target)
+            }),
+        };
+
+        if found {
+            chosen.push(operator.as_ref());
+            return true;
         }
     }
 
-    0
+    false
 }
 
-fn print_formula(target: u64, ops: &Vec<u64>, operators: &mut Vec<&str>) {
-    print!("{} = ", target);
+/// Tries every choice of operators for `ops`, left to right from `total`, until the result is
+/// accepted.
+fn solve_forward<'a>(
+    total: u64,
+    ops: &[u64],
+    operators: &'a [Box<dyn Operator>],
+    chosen: &mut Vec<&'a dyn Operator>,
+    accept: &dyn Fn(u64) -> bool,
+) -> bool {
+    let Some((&next, rest)) = ops.split_first() else {
+        return accept(total);
+    };
 
-    let mut first = true;
-    for op in ops {
-        if !first {
-            print!(" {} ", operators.pop().unwrap());
+    for operator in operators {
+        if let Some(total) = operator.apply(total, next) {
+            chosen.push(operator.as_ref());
+            if solve_forward(total, rest, operators, chosen, accept) {
+                return true;
+            }
+            chosen.pop();
         }
-        first = false;
+    }
+
+    false
+}
 
-        print!("{}", op);
+fn print_formula(target: u64, ops: &[u64], operators: &[&dyn Operator]) {
+    print!("{} = {}", target, ops[0]);
+    for (op, operator) in ops[1..].iter().zip(operators) {
+        print!(" {} {}", operator.symbol(), op);
     }
 
     println!();
@@ -1,15 +1,26 @@
-use std::io::{BufWriter, Write};
-
-use aoclib_rs::{
-    dir::{Dir4, Direction},
-    pad, prep_io, printwriteln, u8_to_string,
+use std::{
+    cmp::Reverse,
+    fs::File,
+    io::{BufWriter, Write},
 };
 
+use aoclib_rs::{pad, prep_io, printwriteln, u8_to_string};
+
+// how many pixels each plot is in a PPM image
+const PPM_SCALE: usize = 8;
+
+// marks padding in the region grid, and plots not labelled yet
+const NO_REGION: u32 = u32::MAX;
+
 struct Region {
     plant: u8,
     area: u64,
     perimiter: u64,
     sides: u64,
+    // bounding box, as inclusive (x, y) corners
+    min: (usize, usize),
+    max: (usize, usize),
+    holes: u64,
 }
 
 impl Region {
@@ -19,80 +30,63 @@ impl Region {
             area: 0,
             perimiter: 0,
             sides: 0,
+            min: (usize::MAX, usize::MAX),
+            max: (0, 0),
+            holes: 0,
         }
     }
-}
 
-#[derive(Copy, Clone)]
-struct SidesCounted {
-    up: bool,
-    down: bool,
-    left: bool,
-    right: bool,
-}
+    /// Adds a plot (in padded coordinates) to the region. Each corner of the region is a corner
+    /// of one of its plots, and it has as many sides as corners, so they're counted plot by plot.
+    fn add_plot(&mut self, contents: &[Vec<u8>], x: usize, y: usize) {
+        let same = |dx: isize, dy: isize| {
+            contents[(y as isize + dy) as usize][(x as isize + dx) as usize] == self.plant
+        };
 
-impl SidesCounted {
-    fn new() -> SidesCounted {
-        SidesCounted {
-            up: false,
-            down: false,
-            left: false,
-            right: false,
-        }
+        self.area += 1;
+        self.perimiter += [(0, -1), (1, 0), (0, 1), (-1, 0)]
+            .iter()
+            .filter(|&&(dx, dy)| !same(dx, dy))
+            .count() as u64;
+        self.sides += [(-1, -1), (1, -1), (1, 1), (-1, 1)]
+            .iter()
+            .filter(|&&(dx, dy)| {
+                let (across, along) = (same(dx, 0), same(0, dy));
+                // outside corner, or inside corner
+                (!across && !along) || (across && along && !same(dx, dy))
+            })
+            .count() as u64;
+
+        // take the padding off
+        self.min = (self.min.0.min(x - 1), self.min.1.min(y - 1));
+        self.max = (self.max.0.max(x - 1), self.max.1.max(y - 1));
     }
 }
 
-pub fn run() {
+pub fn run(sort_by: Option<&str>, image: Option<&str>) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 12).unwrap();
     let contents: Vec<&[u8]> = contents.iter().map(|s| s.as_bytes()).collect();
     let contents = pad(&contents, 1, b' ');
 
-    let mut regions: Vec<Region> = Vec::new();
-    let mut region_grid: Vec<Vec<Option<usize>>> =
-        vec![vec![None; contents[0].len()]; contents.len()];
-
-    for (y, row) in contents.iter().enumerate() {
-        for (x, &plot) in row.iter().enumerate() {
-            if plot == b' ' {
-                continue;
-            }
-
-            if region_grid[y][x].is_none() {
-                let mut visited: Vec<Vec<bool>> =
-                    vec![vec![false; region_grid[0].len()]; region_grid.len()];
-                let mut side_counted =
-                    vec![vec![SidesCounted::new(); region_grid[0].len()]; region_grid.len()];
-
-                regions.push(Region::new(plot));
-
-                let rlast = regions.len() - 1;
-                flood_fill(
-                    &mut regions[rlast],
-                    rlast,
-                    &mut region_grid,
-                    &contents,
-                    (x, y),
-                    &mut visited,
-                    &mut side_counted,
-                );
-            }
-        }
-    }
+    let (mut regions, region_grid) = label_regions(&contents);
+    count_holes(&mut regions, &region_grid);
+    print_regions(&regions, sort_by);
 
     part1(&mut writer, &regions);
     part2(&mut writer, &regions);
+
+    match image {
+        None => {}
+        Some("svg") => write_svg(&regions, &region_grid),
+        Some("ppm") => write_ppm(&region_grid),
+        Some(format) => panic!("unknown image format {}, expected svg or ppm", format),
+    }
 }
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, regions: &Vec<Region>) {
     let mut total = 0;
     for r in regions {
-        println!(
-            "region of {} - area: {}, perimiter: {}",
-            u8_to_string(r.plant),
-            r.area,
-            r.perimiter
-        );
         total += r.area * r.perimiter;
     }
 
@@ -102,153 +96,340 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, regions: &Vec<Region>) {
 fn part2<W: Write>(writer: &mut BufWriter<W>, regions: &Vec<Region>) {
     let mut total = 0;
     for r in regions {
+        total += r.area * r.sides;
+    }
+
+    printwriteln!(writer, "part 2: {}", total).unwrap();
+}
+
+/// Lists the regions in the order they were found, or sorted by plant (A to Z) or by area,
+/// perimeter, sides or holes (biggest first).
+fn print_regions(regions: &[Region], sort_by: Option<&str>) {
+    let mut order: Vec<usize> = (0..regions.len()).collect();
+    match sort_by {
+        None => {}
+        Some("plant") => order.sort_by_key(|&i| regions[i].plant),
+        Some("area") => order.sort_by_key(|&i| Reverse(regions[i].area)),
+        Some("perimeter") => order.sort_by_key(|&i| Reverse(regions[i].perimiter)),
+        Some("sides") => order.sort_by_key(|&i| Reverse(regions[i].sides)),
+        Some("holes") => order.sort_by_key(|&i| Reverse(regions[i].holes)),
+        Some(key) => panic!(
+            "unknown sort key {}, expected plant, area, perimeter, sides or holes",
+            key
+        ),
+    }
+
+    println!(
+        "{:>6} | plant | {:>6} | perimeter | {:>5} | {:>19} | holes",
+        "region", "area", "sides", "bounding box"
+    );
+    for i in order {
+        let r = &regions[i];
         println!(
-            "region of {} - area: {}, sides: {}",
+            "{:>6} | {:>5} | {:>6} | {:>9} | {:>5} | {:>19} | {:>5}",
+            i,
             u8_to_string(r.plant),
             r.area,
-            r.sides
+            r.perimiter,
+            r.sides,
+            format!("{},{} - {},{}", r.min.0, r.min.1, r.max.0, r.max.1),
+            r.holes
         );
-        total += r.area * r.sides;
     }
-
-    printwriteln!(writer, "part 2: {}", total).unwrap();
 }
 
-fn flood_fill(
-    region: &mut Region,
-    region_index: usize,
-    region_grid: &mut Vec<Vec<Option<usize>>>,
-    contents: &Vec<Vec<u8>>,
-    loc: (usize, usize),
-    visited: &mut Vec<Vec<bool>>,
-    side_counted: &mut Vec<Vec<SidesCounted>>,
-) {
-    let (x, y) = loc;
-    if visited[y][x] {
-        return;
+/// Counts holes using the Euler characteristic of each region, taking its plots as closed
+/// squares: corners - edges + plots = 1 - holes. Every corner and edge is looked at once, and
+/// counted for each different region that touches it.
+fn count_holes(regions: &mut [Region], region_grid: &[Vec<u32>]) {
+    let (width, height) = (region_grid[0].len(), region_grid.len());
+    let mut euler: Vec<i64> = regions.iter().map(|r| r.area as i64).collect();
+    let mut count = |cells: &[u32], by: i64| {
+        for (i, &r) in cells.iter().enumerate() {
+            if r != NO_REGION && !cells[..i].contains(&r) {
+                euler[r as usize] += by;
+            }
+        }
+    };
+
+    for y in 1..height {
+        for x in 1..width {
+            count(
+                &[
+                    region_grid[y - 1][x - 1],
+                    region_grid[y - 1][x],
+                    region_grid[y][x - 1],
+                    region_grid[y][x],
+                ],
+                1,
+            );
+        }
+    }
+    for y in 0..height {
+        for x in 0..width {
+            if y > 0 {
+                count(&[region_grid[y - 1][x], region_grid[y][x]], -1);
+            }
+            if x > 0 {
+                count(&[region_grid[y][x - 1], region_grid[y][x]], -1);
+            }
+        }
     }
 
-    if contents[y][x] != region.plant {
-        return;
+    for (region, e) in regions.iter_mut().zip(euler) {
+        region.holes = (1 - e) as u64;
     }
+}
 
-    region_grid[y][x] = Some(region_index);
+/// Spreads the regions' colours round the colour wheel by the golden angle, so neighbouring
+/// regions, which were found one after another, look different.
+fn region_colour(region: usize) -> (u8, u8, u8) {
+    let hue = (region as f64 * 0.618_033_988_75).fract() * 6.0;
+    let (s, v) = (0.55, 0.95);
+    let f = hue.fract();
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+    let (r, g, b) = match hue as u8 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
 
-    region.area += 1;
-    region.perimiter += get_perimiter(region.plant, contents, x, y);
+    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
 
-    visited[y][x] = true;
+/// Writes outputs/12.svg, with a square per plot and a line for each stretch of fence.
+fn write_svg(regions: &[Region], region_grid: &[Vec<u32>]) {
+    let (width, height) = (region_grid[0].len() - 2, region_grid.len() - 2);
+    let mut writer = BufWriter::new(File::create("outputs/12.svg").unwrap());
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        width, height
+    )
+    .unwrap();
+
+    for (y, row) in region_grid.iter().enumerate().skip(1).take(height) {
+        for (x, cell) in row.iter().enumerate().skip(1).take(width) {
+            let r = *cell as usize;
+            let (red, green, blue) = region_colour(r);
+            writeln!(
+                writer,
+                r#"<rect x="{}" y="{}" width="1" height="1" fill="rgb({},{},{})"><title>{}</title></rect>"#,
+                x - 1,
+                y - 1,
+                red,
+                green,
+                blue,
+                u8_to_string(regions[r].plant)
+            )
+            .unwrap();
+        }
+    }
 
-    for dir in [
-        (side_counted[y][x].up, Dir4::Up),
-        (side_counted[y][x].left, Dir4::Left),
-        (side_counted[y][x].down, Dir4::Down),
-        (side_counted[y][x].right, Dir4::Right),
-    ] {
-        if !dir.0 {
-            count_side(region, contents, loc, side_counted, dir.1);
+    writeln!(
+        writer,
+        r#"<g stroke="black" stroke-width="0.1" stroke-linecap="square">"#
+    )
+    .unwrap();
+    for y in 1..=(height + 1) {
+        for x in 1..=(width + 1) {
+            if region_grid[y][x] != region_grid[y - 1][x] {
+                writeln!(
+                    writer,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                    x - 1,
+                    y - 1,
+                    x,
+                    y - 1
+                )
+                .unwrap();
+            }
+            if region_grid[y][x] != region_grid[y][x - 1] {
+                writeln!(
+                    writer,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                    x - 1,
+                    y - 1,
+                    x - 1,
+                    y
+                )
+                .unwrap();
+            }
         }
     }
+    writeln!(writer, "</g>").unwrap();
+    writeln!(writer, "</svg>").unwrap();
+}
 
-    for loc in Dir4::iter_valid_usizes_deltas((x, y), (contents[0].len(), contents.len())) {
-        flood_fill(
-            region,
-            region_index,
-            region_grid,
-            contents,
-            loc,
-            visited,
-            side_counted,
-        );
+/// Writes outputs/12.ppm, with each plot as a square of pixels and fences in black along the
+/// edges of plots next to a different region.
+fn write_ppm(region_grid: &[Vec<u32>]) {
+    let (width, height) = (region_grid[0].len() - 2, region_grid.len() - 2);
+    let mut writer = BufWriter::new(File::create("outputs/12.ppm").unwrap());
+    writeln!(
+        writer,
+        "P6\n{} {}\n255",
+        width * PPM_SCALE,
+        height * PPM_SCALE
+    )
+    .unwrap();
+
+    for py in 0..(height * PPM_SCALE) {
+        let (y, dy) = (py / PPM_SCALE + 1, py % PPM_SCALE);
+        for px in 0..(width * PPM_SCALE) {
+            let (x, dx) = (px / PPM_SCALE + 1, px % PPM_SCALE);
+            let r = region_grid[y][x];
+            let fence = (dy == 0 && region_grid[y - 1][x] != r)
+                || (dy == PPM_SCALE - 1 && region_grid[y + 1][x] != r)
+                || (dx == 0 && region_grid[y][x - 1] != r)
+                || (dx == PPM_SCALE - 1 && region_grid[y][x + 1] != r);
+
+            let (red, green, blue) = if fence {
+                (0, 0, 0)
+            } else {
+                region_colour(r as usize)
+            };
+            writer.write_all(&[red, green, blue]).unwrap();
+        }
     }
 }
 
-fn get_perimiter(plant: u8, contents: &[Vec<u8>], x: usize, y: usize) -> u64 {
-    let mut total = 0;
-    for (x, y) in Dir4::iter_valid_usizes_deltas((x, y), (contents[0].len(), contents.len())) {
-        if contents[y][x] != plant {
-            total += 1;
+/// Labels every plot with its region. Each region is filled a row at a time from a stack of
+/// seeds rather than by recursion, so big regions can't overflow the call stack, and the region
+/// grid doubles as the record of which plots have been visited.
+fn label_regions(contents: &[Vec<u8>]) -> (Vec<Region>, Vec<Vec<u32>>) {
+    let (width, height) = (contents[0].len(), contents.len());
+    let mut regions = Vec::new();
+    let mut region_grid = vec![vec![NO_REGION; width]; height];
+    let mut seeds = Vec::new();
+
+    for y in 1..(height - 1) {
+        for x in 1..(width - 1) {
+            if region_grid[y][x] != NO_REGION {
+                continue;
+            }
+
+            let index = regions.len() as u32;
+            let plant = contents[y][x];
+            let mut region = Region::new(plant);
+            let fillable = |region_grid: &[Vec<u32>], x: usize, y: usize| {
+                contents[y][x] == plant && region_grid[y][x] == NO_REGION
+            };
+
+            seeds.push((x, y));
+            while let Some((x, y)) = seeds.pop() {
+                if !fillable(&region_grid, x, y) {
+                    continue;
+                }
+
+                // the padding stops these running off the edge
+                let mut left = x;
+                while fillable(&region_grid, left - 1, y) {
+                    left -= 1;
+                }
+                let mut right = x;
+                while fillable(&region_grid, right + 1, y) {
+                    right += 1;
+                }
+
+                for (x, label) in region_grid[y]
+                    .iter_mut()
+                    .enumerate()
+                    .take(right + 1)
+                    .skip(left)
+                {
+                    *label = index;
+                    region.add_plot(contents, x, y);
+                }
+
+                // one seed for each run of the plant still to fill above and below
+                for ny in [y - 1, y + 1] {
+                    let mut in_run = false;
+                    for x in left..=right {
+                        let f = fillable(&region_grid, x, ny);
+                        if f && !in_run {
+                            seeds.push((x, ny));
+                        }
+                        in_run = f;
+                    }
+                }
+            }
+
+            regions.push(region);
         }
     }
 
-    total
+    (regions, region_grid)
 }
 
-fn count_side(
-    region: &mut Region,
-    contents: &[Vec<u8>],
-    loc: (usize, usize),
-    side_counted: &mut [Vec<SidesCounted>],
-    dir: Dir4,
-) {
-    let (x, y) = loc;
-    let (move_dir_1, move_dir_2, next_x, next_y) = match dir {
-        Dir4::Up | Dir4::Down => (Dir4::Left, Dir4::Right, x + 1, y),
-        Dir4::Left | Dir4::Right => (Dir4::Up, Dir4::Down, x, y + 1),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let (wall_x, wall_y) = match dir {
-        Dir4::Up => (x, y - 1),
-        Dir4::Down => (x, y + 1),
-        Dir4::Left => (x - 1, y),
-        Dir4::Right => (x + 1, y),
-    };
+    fn regions(garden: &[&str]) -> Vec<Region> {
+        let contents: Vec<&[u8]> = garden.iter().map(|s| s.as_bytes()).collect();
+        let contents = pad(&contents, 1, b' ');
 
-    if contents[wall_y][wall_x] != region.plant {
-        println!(
-            "region {} sides += 1 ({:?})",
-            u8_to_string(region.plant),
-            dir
-        );
+        let (mut regions, region_grid) = label_regions(&contents);
+        count_holes(&mut regions, &region_grid);
 
-        region.sides += 1;
+        regions
+    }
 
-        count_side_internal(region, contents, loc, side_counted, dir, move_dir_1);
-        count_side_internal(
-            region,
-            contents,
-            (next_x, next_y),
-            side_counted,
-            dir,
-            move_dir_2,
-        );
+    fn prices(regions: &[Region]) -> (u64, u64) {
+        (
+            regions.iter().map(|r| r.area * r.perimiter).sum(),
+            regions.iter().map(|r| r.area * r.sides).sum(),
+        )
     }
-}
 
-fn count_side_internal(
-    region: &mut Region,
-    contents: &[Vec<u8>],
-    loc: (usize, usize),
-    side_counted: &mut [Vec<SidesCounted>],
-    wall_dir: Dir4,
-    move_dir: Dir4,
-) {
-    let (mut x, mut y) = loc;
-    println!(
-        "original: {} {} (region {})",
-        x,
-        y,
-        u8_to_string(region.plant),
-    );
+    fn find(regions: &[Region], plant: u8) -> &Region {
+        regions.iter().find(|r| r.plant == plant).unwrap()
+    }
 
-    loop {
-        if contents[y][x] != region.plant {
-            break;
-        }
+    #[test]
+    fn small_example() {
+        let regions = regions(&["AAAA", "BBCD", "BBCC", "EEEC"]);
+        assert_eq!(regions.len(), 5);
+        assert_eq!(prices(&regions), (140, 80));
+        assert_eq!(find(&regions, b'A').sides, 4);
+        assert_eq!(find(&regions, b'C').sides, 8);
+        assert!(regions.iter().all(|r| r.holes == 0));
+    }
 
-        match wall_dir {
-            Dir4::Up => side_counted[y][x].up = true,
-            Dir4::Down => side_counted[y][x].down = true,
-            Dir4::Left => side_counted[y][x].left = true,
-            Dir4::Right => side_counted[y][x].right = true,
-        };
-        println!("marked wall at {} {} (dir {:?}) counted", x, y, wall_dir);
+    #[test]
+    fn region_with_holes() {
+        let regions = regions(&["OOOOO", "OXOXO", "OOOOO", "OXOXO", "OOOOO"]);
+        assert_eq!(prices(&regions), (772, 436));
+
+        let o = find(&regions, b'O');
+        assert_eq!((o.area, o.perimiter, o.sides, o.holes), (21, 36, 20, 4));
+        assert_eq!((o.min, o.max), ((0, 0), (4, 4)));
+        assert!(regions
+            .iter()
+            .filter(|r| r.plant == b'X')
+            .all(|r| (r.area, r.sides, r.holes) == (1, 4, 0)));
+    }
 
-        let (wall_x, wall_y) = wall_dir.apply_delta_to_usizes((x, y));
-        if contents[wall_y][wall_x] == region.plant {
-            break;
-        }
+    #[test]
+    fn e_shaped_region() {
+        let regions = regions(&["EEEEE", "EXXXX", "EEEEE", "EXXXX", "EEEEE"]);
+        assert_eq!(prices(&regions).1, 236);
+        assert_eq!(find(&regions, b'E').sides, 12);
+        assert_eq!(find(&regions, b'E').holes, 0);
+    }
+
+    #[test]
+    fn holes_meeting_at_a_corner() {
+        let regions = regions(&["AAAAAA", "AAABBA", "AAABBA", "ABBAAA", "ABBAAA", "AAAAAA"]);
+        assert_eq!(prices(&regions).1, 368);
 
-        (x, y) = move_dir.apply_delta_to_usizes((x, y));
+        // the two B regions only touch diagonally, where A's plots also meet, so they're
+        // separate holes
+        let a = find(&regions, b'A');
+        assert_eq!((a.sides, a.holes), (12, 2));
     }
 }
@@ -3,81 +3,103 @@ use std::io::{BufWriter, Write};
 use aoclib_rs::{prep_io, printwriteln};
 
 /*
-   Button A: X+c1, Y+d1
-   Button B: X+c2, Y+d2
+   Button 1: X+c1, Y+d1
+   ...
+   Button N: X+cN, Y+dN
    Prize: X=a, Y=b
 
-   variables v1 (times you press button A), v2 (times you press button B):
-   a = c1 * v1 + c2 * v2
-   b = d1 * v1 + d2 * v2
+   variables v1..vN (times you press each button):
+   a = c1 * v1 + ... + cN * vN
+   b = d1 * v1 + ... + dN * vN
 
-   v1 = (a - c2 * v2) / c1, where c1 != 0
-   v1 = a / c1 - (c2 / c1) * v2, where c1 != 0
+   minimize cost1 * v1 + ... + costN * vN, where every vi is a non-negative integer.
 
-   b = d1 * (a / c1 - (c2 / c1) * v2) + d2 * v2, where c1 != 0
-   b = d1 * a / c1 - d1 * (c2 / c1) * v2 + d2 * v2, where c1 != 0
-   b = d1 * a / c1 + (-d1 * (c2 / c1) + d2) * v2, where c1 != 0
-   v2 = (b - d1 * a / c1) / (d2 - d1 * c2 / c1), where c1 != 0
+   Unimodular column operations (tracked in U) bring the 2xN system into echelon form
+   A * U = [L | 0], where L has one column per independent equation. Solving L * y = (a, b)
+   gives a particular solution U * (y, 0), and the remaining columns of U form a basis of
+   the integer kernel. Every integer solution is then v = v0 + K * t for some integer vector
+   t, so the problem becomes minimizing a linear cost over the integer points of the
+   polytope v0 + K * t >= 0.
+
+   With two buttons K is empty and the solution is unique. With three buttons t is a single
+   integer on an interval, and a linear cost is minimized at one of its ends. More buttons
+   enumerate the outer parameters within bounds obtained by Fourier-Motzkin elimination.
+
+   To keep that enumeration small, the search is restricted to a box around the optimum of
+   the LP relaxation, which is a basic solution using at most two buttons. Cook, Gerards,
+   Schrijver and Tardos (1986) showed that some optimal integer solution lies within
+   n * delta of it in every coordinate, where delta is the largest absolute subdeterminant
+   of the button matrix.
 */
 
 struct Prize {
-    btn_a: Button,
-    btn_b: Button,
+    buttons: Vec<Button>,
     prz: PrizeCoords,
 }
 
 impl Prize {
-    fn new(input: &[&str]) -> Prize {
-        let i1 = input[0].strip_prefix("Button A: X+").unwrap();
-        let i2 = input[1].strip_prefix("Button B: X+").unwrap();
-        let i3 = input[2].strip_prefix("Prize: X=").unwrap();
+    fn new(input: &[&str], costs: &[u64]) -> Prize {
+        let (prize_line, button_lines) = input.split_last().unwrap();
 
-        let mut i1s = i1.split(", Y+");
-        let mut i2s = i2.split(", Y+");
-        let mut i3s = i3.split(", Y=");
+        let mut buttons = Vec::with_capacity(button_lines.len());
+        for (i, line) in button_lines.iter().enumerate() {
+            let (label, deltas) = line
+                .strip_prefix("Button ")
+                .unwrap()
+                .split_once(": X+")
+                .unwrap();
+            let mut ds = deltas.split(", Y+");
 
-        let c1 = i1s.next().unwrap().parse().unwrap();
-        let d1 = i1s.next().unwrap().parse().unwrap();
+            let c = ds.next().unwrap().parse().unwrap();
+            let d = ds.next().unwrap().parse().unwrap();
 
-        let c2 = i2s.next().unwrap().parse().unwrap();
-        let d2 = i2s.next().unwrap().parse().unwrap();
+            let cost = match costs.get(i) {
+                Some(&cost) => cost as i128,
+                None => panic!("no cost configured for button {}", label),
+            };
+
+            buttons.push(Button { c, d, cost });
+        }
 
-        let a = i3s.next().unwrap().parse().unwrap();
-        let b = i3s.next().unwrap().parse().unwrap();
+        let mut ps = prize_line.strip_prefix("Prize: X=").unwrap().split(", Y=");
+
+        let a = ps.next().unwrap().parse().unwrap();
+        let b = ps.next().unwrap().parse().unwrap();
 
         Prize {
-            btn_a: Button { c: c1, d: d1 },
-            btn_b: Button { c: c2, d: d2 },
+            buttons,
             prz: PrizeCoords { a, b },
         }
     }
 }
 
 struct Button {
-    c: f64,
-    d: f64,
+    c: i128,
+    d: i128,
+    cost: i128,
 }
 
 struct PrizeCoords {
-    a: f64,
-    b: f64,
+    a: i128,
+    b: i128,
+}
+
+/// A constraint of the form `coeffs . t + constant >= 0`.
+#[derive(Clone)]
+struct Constraint {
+    coeffs: Vec<i128>,
+    constant: i128,
 }
 
-pub fn run() {
+pub fn run(costs: &[u64]) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 13).unwrap();
-    let mut contents: &[&str] = &contents;
 
-    let mut prizes = Vec::new();
-    loop {
-        prizes.push(Prize::new(&contents[..3]));
-
-        if contents.len() > 4 {
-            contents = &contents[4..];
-        } else {
-            break;
-        }
-    }
+    let mut prizes: Vec<Prize> = contents
+        .split(|line| line.is_empty())
+        .filter(|machine| !machine.is_empty())
+        .map(|machine| Prize::new(machine, costs))
+        .collect();
 
     part1(&mut writer, &prizes);
     part2(&mut writer, &mut prizes);
@@ -89,34 +111,431 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, prizes: &[Prize]) {
 
 fn part2<W: Write>(writer: &mut BufWriter<W>, prizes: &mut [Prize]) {
     for prize in prizes.iter_mut() {
-        prize.prz.a += 10_000_000_000_000.0;
-        prize.prz.b += 10_000_000_000_000.0;
+        prize.prz.a += 10_000_000_000_000;
+        prize.prz.b += 10_000_000_000_000;
     }
     printwriteln!(writer, "part 2: {}", compute_button_presses_total(prizes)).unwrap();
 }
 
-fn compute_button_presses_total(prizes: &[Prize]) -> i64 {
+fn compute_button_presses_total(prizes: &[Prize]) -> i128 {
     let mut total = 0;
     for prize in prizes {
-        let (a, b, c1, d1, c2, d2) = (
-            prize.prz.a,
-            prize.prz.b,
-            prize.btn_a.c,
-            prize.btn_a.d,
-            prize.btn_b.c,
-            prize.btn_b.d,
-        );
-        let v2 = (b - d1 * a / c1) / (d2 - d1 * c2 / c1);
-        let v1 = a / c1 - (c2 / c1) * v2;
-
-        if is_probably_int(v1) && is_probably_int(v2) && v1 >= 0.0 && v2 >= 0.0 {
-            total += v1.round() as i64 * 3 + v2.round() as i64;
+        if let Some((cost, _)) = min_cost_presses(prize) {
+            total += cost;
         }
     }
 
     total
 }
 
-fn is_probably_int(f: f64) -> bool {
-    f.fract().abs() < 0.001 || f.fract().abs() > 0.999
+/// Finds the cheapest non-negative integer number of presses of each button that reaches the
+/// prize, returning the total cost and the presses per button.
+fn min_cost_presses(prize: &Prize) -> Option<(i128, Vec<i128>)> {
+    let n = prize.buttons.len();
+    let mut rows = [
+        prize.buttons.iter().map(|b| b.c).collect::<Vec<_>>(),
+        prize.buttons.iter().map(|b| b.d).collect::<Vec<_>>(),
+    ];
+    let target = [prize.prz.a, prize.prz.b];
+
+    let mut u: Vec<Vec<i128>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1 } else { 0 }).collect())
+        .collect();
+
+    // column-reduce each row so that only its pivot column is non-zero beyond the previous
+    // pivots
+    let mut pivots = Vec::with_capacity(2);
+    for row in 0..rows.len() {
+        let p = pivots.len();
+        loop {
+            let smallest = (p..n)
+                .filter(|&col| rows[row][col] != 0)
+                .min_by_key(|&col| rows[row][col].abs());
+            let Some(smallest) = smallest else {
+                break;
+            };
+            swap_columns(&mut rows, &mut u, p, smallest);
+
+            let mut done = true;
+            for col in (p + 1)..n {
+                let q = rows[row][col] / rows[row][p];
+                if q != 0 {
+                    add_column_multiple(&mut rows, &mut u, col, p, -q);
+                }
+                done &= rows[row][col] == 0;
+            }
+
+            if done {
+                break;
+            }
+        }
+
+        if p < n && rows[row][p] != 0 {
+            pivots.push(row);
+        }
+    }
+
+    // forward-substitute for the pivot variables, checking non-pivot rows for consistency
+    let rank = pivots.len();
+    let mut y = vec![0; rank];
+    for (row, &target) in target.iter().enumerate() {
+        let solved = pivots.iter().position(|&p| p == row);
+        let limit = solved.unwrap_or(rank);
+        let rest = target - (0..limit).map(|q| rows[row][q] * y[q]).sum::<i128>();
+        match solved {
+            Some(p) => {
+                if rest % rows[row][p] != 0 {
+                    return None;
+                }
+                y[p] = rest / rows[row][p];
+            }
+            None => {
+                if rest != 0 {
+                    return None;
+                }
+            }
+        }
+    }
+
+    // v = v0 + K * t, expressed as one constraint v_i >= 0 per button
+    let mut constraints: Vec<Constraint> = u
+        .iter()
+        .map(|u_row| Constraint {
+            coeffs: u_row[rank..].to_vec(),
+            constant: (0..rank).map(|q| u_row[q] * y[q]).sum(),
+        })
+        .collect();
+    if rank < n {
+        constraints.extend(proximity_box(prize, &u, rank, &y)?);
+    }
+
+    let costs: Vec<i128> = (rank..n)
+        .map(|col| (0..n).map(|i| prize.buttons[i].cost * u[i][col]).sum())
+        .collect();
+
+    let t = minimize(&constraints, &costs)?;
+    let presses: Vec<i128> = constraints[..n]
+        .iter()
+        .map(|c| c.constant + dot(&c.coeffs, &t))
+        .collect();
+    let cost = presses
+        .iter()
+        .zip(&prize.buttons)
+        .map(|(v, b)| v * b.cost)
+        .sum();
+
+    Some((cost, presses))
+}
+
+/// Constraints keeping every button within n * delta presses of the LP relaxation's optimum.
+fn proximity_box(
+    prize: &Prize,
+    u: &[Vec<i128>],
+    rank: usize,
+    y: &[i128],
+) -> Option<Vec<Constraint>> {
+    let (a, b) = (prize.prz.a, prize.prz.b);
+    let buttons = &prize.buttons;
+    let n = buttons.len();
+
+    // every basic solution is a (numerators, denominator) pair
+    let mut basic_solutions = Vec::new();
+    for i in 0..n {
+        let (ci, di) = (buttons[i].c, buttons[i].d);
+        if ci != 0 && ci * b == di * a {
+            let mut nums = vec![0; n];
+            nums[i] = a;
+            basic_solutions.push((nums, ci));
+        } else if ci == 0 && a == 0 && di != 0 {
+            let mut nums = vec![0; n];
+            nums[i] = b;
+            basic_solutions.push((nums, di));
+        }
+
+        for j in (i + 1)..n {
+            let (cj, dj) = (buttons[j].c, buttons[j].d);
+            let det = ci * dj - cj * di;
+            if det == 0 {
+                continue;
+            }
+
+            let mut nums = vec![0; n];
+            nums[i] = a * dj - b * cj;
+            nums[j] = b * ci - a * di;
+            basic_solutions.push((nums, det));
+        }
+    }
+
+    let (nums, den) = basic_solutions
+        .into_iter()
+        .map(|(nums, den)| {
+            let sign = den.signum();
+            (
+                nums.iter().map(|x| x * sign).collect::<Vec<_>>(),
+                den * sign,
+            )
+        })
+        .filter(|(nums, _)| nums.iter().all(|&x| x >= 0))
+        .min_by(|(n1, d1), (n2, d2)| {
+            let c1: i128 = n1.iter().zip(buttons).map(|(x, b)| x * b.cost).sum();
+            let c2: i128 = n2.iter().zip(buttons).map(|(x, b)| x * b.cost).sum();
+            (c1 * d2).cmp(&(c2 * d1))
+        })?;
+
+    let mut delta = 0;
+    for (i, bi) in buttons.iter().enumerate() {
+        delta = delta.max(bi.c.abs()).max(bi.d.abs());
+        for bj in &buttons[(i + 1)..] {
+            delta = delta.max((bi.c * bj.d - bj.c * bi.d).abs());
+        }
+    }
+    let radius = n as i128 * delta;
+
+    let mut constraints = Vec::with_capacity(2 * n);
+    for (i, u_row) in u.iter().enumerate() {
+        let coeffs = u_row[rank..].to_vec();
+        let constant: i128 = (0..rank).map(|q| u_row[q] * y[q]).sum();
+        let lo = -(radius * den - nums[i]).div_euclid(den);
+        let hi = (nums[i] + radius * den).div_euclid(den);
+
+        // v_i - lo >= 0
+        constraints.push(Constraint {
+            coeffs: coeffs.clone(),
+            constant: constant - lo,
+        });
+        // hi - v_i >= 0
+        constraints.push(Constraint {
+            coeffs: coeffs.iter().map(|x| -x).collect(),
+            constant: hi - constant,
+        });
+    }
+
+    Some(constraints)
+}
+
+fn swap_columns(rows: &mut [Vec<i128>], u: &mut [Vec<i128>], a: usize, b: usize) {
+    for row in rows.iter_mut().chain(u.iter_mut()) {
+        row.swap(a, b);
+    }
+}
+
+/// col[dst] += m * col[src]
+fn add_column_multiple(
+    rows: &mut [Vec<i128>],
+    u: &mut [Vec<i128>],
+    dst: usize,
+    src: usize,
+    m: i128,
+) {
+    for row in rows.iter_mut().chain(u.iter_mut()) {
+        row[dst] += m * row[src];
+    }
+}
+
+/// Minimizes `costs . t` over integer vectors `t` satisfying every constraint.
+fn minimize(constraints: &[Constraint], costs: &[i128]) -> Option<Vec<i128>> {
+    if costs.is_empty() {
+        return if constraints.iter().all(|c| c.constant >= 0) {
+            Some(Vec::new())
+        } else {
+            None
+        };
+    }
+
+    // bound the first parameter by eliminating all the others
+    let mut projected = constraints.to_vec();
+    for var in (1..costs.len()).rev() {
+        projected = eliminate(&projected, var);
+    }
+    let (lo, hi) = bounds(&projected)?;
+
+    if costs.len() == 1 {
+        let t = if costs[0] >= 0 { lo } else { hi };
+        return Some(vec![t]);
+    }
+
+    let mut best: Option<(i128, Vec<i128>)> = None;
+    for t0 in lo..=hi {
+        let substituted: Vec<Constraint> = constraints
+            .iter()
+            .map(|c| Constraint {
+                coeffs: c.coeffs[1..].to_vec(),
+                constant: c.constant + c.coeffs[0] * t0,
+            })
+            .collect();
+
+        if let Some(rest) = minimize(&substituted, &costs[1..]) {
+            let mut t = vec![t0];
+            t.extend(rest);
+            let cost = dot(costs, &t);
+            let better = match &best {
+                Some((best_cost, _)) => cost < *best_cost,
+                None => true,
+            };
+            if better {
+                best = Some((cost, t));
+            }
+        }
+    }
+
+    best.map(|(_, t)| t)
+}
+
+/// Fourier-Motzkin elimination of `var` from the constraints.
+fn eliminate(constraints: &[Constraint], var: usize) -> Vec<Constraint> {
+    let mut out = Vec::new();
+    let (mut pos, mut neg) = (Vec::new(), Vec::new());
+    for c in constraints {
+        match c.coeffs[var].signum() {
+            1 => pos.push(c),
+            -1 => neg.push(c),
+            _ => out.push(without(c, var)),
+        }
+    }
+
+    for p in &pos {
+        for n in &neg {
+            let (mp, mn) = (-n.coeffs[var], p.coeffs[var]);
+            let mut combined = Constraint {
+                coeffs: p
+                    .coeffs
+                    .iter()
+                    .zip(&n.coeffs)
+                    .map(|(a, b)| mp * a + mn * b)
+                    .collect(),
+                constant: mp * p.constant + mn * n.constant,
+            };
+            normalize(&mut combined);
+            out.push(without(&combined, var));
+        }
+    }
+
+    out
+}
+
+fn without(c: &Constraint, var: usize) -> Constraint {
+    let mut coeffs = c.coeffs.clone();
+    coeffs.remove(var);
+    Constraint {
+        coeffs,
+        constant: c.constant,
+    }
+}
+
+fn normalize(c: &mut Constraint) {
+    let g = c.coeffs.iter().fold(c.constant, |acc, &x| gcd(acc, x));
+    if g > 1 {
+        c.coeffs.iter_mut().for_each(|x| *x /= g);
+        c.constant /= g;
+    }
+}
+
+/// The integer range allowed for a single parameter by single-variable constraints.
+fn bounds(constraints: &[Constraint]) -> Option<(i128, i128)> {
+    let (mut lo, mut hi) = (i128::MIN, i128::MAX);
+    for c in constraints {
+        let (a, b) = (c.coeffs[0], c.constant);
+        match a.signum() {
+            1 => lo = lo.max(-b.div_euclid(a)),
+            -1 => hi = hi.min(b.div_euclid(-a)),
+            _ => {
+                if b < 0 {
+                    return None;
+                }
+            }
+        }
+    }
+
+    if lo == i128::MIN || hi == i128::MAX {
+        panic!("unbounded number of button presses");
+    }
+
+    if lo > hi {
+        None
+    } else {
+        Some((lo, hi))
+    }
+}
+
+fn dot(a: &[i128], b: &[i128]) -> i128 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [[&str; 3]; 4] = [
+        [
+            "Button A: X+94, Y+34",
+            "Button B: X+22, Y+67",
+            "Prize: X=8400, Y=5400",
+        ],
+        [
+            "Button A: X+26, Y+66",
+            "Button B: X+67, Y+21",
+            "Prize: X=12748, Y=12176",
+        ],
+        [
+            "Button A: X+17, Y+86",
+            "Button B: X+84, Y+37",
+            "Prize: X=7870, Y=6450",
+        ],
+        [
+            "Button A: X+69, Y+23",
+            "Button B: X+27, Y+71",
+            "Prize: X=18641, Y=10279",
+        ],
+    ];
+
+    fn example_prizes() -> Vec<Prize> {
+        EXAMPLE.iter().map(|m| Prize::new(m, &[3, 1])).collect()
+    }
+
+    #[test]
+    fn example_presses() {
+        let prizes = example_prizes();
+        assert_eq!(min_cost_presses(&prizes[0]), Some((280, vec![80, 40])));
+        assert_eq!(min_cost_presses(&prizes[1]), None);
+        assert_eq!(min_cost_presses(&prizes[2]), Some((200, vec![38, 86])));
+        assert_eq!(min_cost_presses(&prizes[3]), None);
+        assert_eq!(compute_button_presses_total(&prizes), 480);
+    }
+
+    #[test]
+    fn example_far_prizes() {
+        let mut prizes = example_prizes();
+        for prize in &mut prizes {
+            prize.prz.a += 10_000_000_000_000;
+            prize.prz.b += 10_000_000_000_000;
+        }
+        let solvable: Vec<bool> = prizes
+            .iter()
+            .map(|p| min_cost_presses(p).is_some())
+            .collect();
+        assert_eq!(solvable, [false, true, false, true]);
+    }
+
+    #[test]
+    fn third_button_is_used_when_cheaper() {
+        // A: (1, 0) for 3, B: (0, 1) for 1, C: (1, 1) for 1
+        let prize = Prize::new(
+            &[
+                "Button A: X+1, Y+0",
+                "Button B: X+0, Y+1",
+                "Button C: X+1, Y+1",
+                "Prize: X=5, Y=7",
+            ],
+            &[3, 1, 1],
+        );
+        assert_eq!(min_cost_presses(&prize), Some((7, vec![0, 2, 5])));
+    }
 }
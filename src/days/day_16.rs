@@ -1,18 +1,94 @@
 use std::{
-    collections::HashSet,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
     io::{BufWriter, Write},
+    marker::PhantomData,
 };
 
 use aoclib_rs::{
     dijkstra::{Dijkstrable, PqElement},
-    dir::{Dir4, Direction},
-    prep_io, printwriteln,
+    dir::{Dir4, Dir8, Direction},
+    prep_io, printwriteln, u8_to_string,
 };
 
+/// A direction the reindeer can face, with every value listed clockwise from up. Each tile keeps
+/// a node per heading, in this order.
+trait Heading: Direction + Eq + 'static {
+    const ALL: &'static [Self];
+}
+
+impl Heading for Dir4 {
+    const ALL: &'static [Dir4] = &[Dir4::Up, Dir4::Right, Dir4::Down, Dir4::Left];
+}
+
+impl Heading for Dir8 {
+    const ALL: &'static [Dir8] = &[
+        Dir8::Up,
+        Dir8::UpRight,
+        Dir8::Right,
+        Dir8::DownRight,
+        Dir8::Down,
+        Dir8::DownLeft,
+        Dir8::Left,
+        Dir8::UpLeft,
+    ];
+}
+
+type State<D> = (usize, usize, D);
+
+/// The costs and moves the reindeer is allowed, and where it starts and finishes. Facings are
+/// kept as (x, y) deltas so that they fit either kind of heading. With no end facing, reaching E
+/// facing any way will do, as in the puzzle.
+#[derive(Copy, Clone)]
+pub struct Rules {
+    step_cost: u32,
+    turn_cost: u32,
+    u_turn_cost: Option<u32>,
+    diagonal: bool,
+    start_facing: (i64, i64),
+    end_facing: Option<(i64, i64)>,
+}
+
+impl Rules {
+    pub fn new(
+        step_cost: u32,
+        turn_cost: u32,
+        u_turn_cost: Option<u32>,
+        diagonal: bool,
+        start_facing: &str,
+        end_facing: Option<&str>,
+    ) -> Rules {
+        if step_cost == 0 || turn_cost == 0 || u_turn_cost == Some(0) {
+            panic!("move costs must be positive");
+        }
+
+        Rules {
+            step_cost,
+            turn_cost,
+            u_turn_cost,
+            diagonal,
+            start_facing: parse_facing(start_facing, diagonal),
+            end_facing: end_facing.map(|f| parse_facing(f, diagonal)),
+        }
+    }
+
+    fn start_heading<D: Heading>(&self) -> D {
+        heading(self.start_facing)
+    }
+
+    fn end_headings<D: Heading>(&self) -> Vec<D> {
+        match self.end_facing {
+            Some(f) => vec![heading(f)],
+            None => D::ALL.to_vec(),
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 struct Node {
     val: u8,
     distance: Option<u32>,
+    end_distance: Option<u32>,
     visited: bool,
 }
 
@@ -21,129 +97,454 @@ impl Node {
         Node {
             val,
             distance: None,
+            end_distance: None,
             visited: false,
         }
     }
 }
 
-struct Map<'a>(&'a mut Vec<Vec<Vec<Node>>>);
+struct Map<'a, D>(&'a mut Vec<Vec<Vec<Node>>>, PhantomData<D>);
+
+impl<'a, D: Heading> Dijkstrable for Map<'a, D> {
+    type Point = State<D>;
+    type Bounds = Rules;
+    type Dist = u32;
+    type PQE = PqElement<State<D>, u32>;
+
+    fn neighbours(
+        p: Self::Point,
+        rules: Self::Bounds,
+    ) -> impl Iterator<Item = (Self::Point, Self::Dist)> {
+        neighbours(p, &rules).into_iter()
+    }
+
+    fn is_impossible(&self, p: Self::Point) -> bool {
+        self.0[p.1][p.0][index(p.2)].val == b'#'
+    }
+
+    fn dist(&self, p: Self::Point) -> Option<Self::Dist> {
+        self.0[p.1][p.0][index(p.2)].distance
+    }
+
+    fn set_dist(&mut self, p: Self::Point, dist: Option<Self::Dist>) {
+        self.0[p.1][p.0][index(p.2)].distance = dist;
+    }
+}
+
+/// Distances to the end rather than from the start, found by searching backwards from the end.
+struct ReverseMap<'a, D>(&'a mut Vec<Vec<Vec<Node>>>, PhantomData<D>);
 
-impl<'a> Dijkstrable for Map<'a> {
-    type Point = (usize, usize, Dir4);
-    type Bounds = ();
+impl<'a, D: Heading> Dijkstrable for ReverseMap<'a, D> {
+    type Point = State<D>;
+    type Bounds = Rules;
     type Dist = u32;
-    type PQE = PqElement<(usize, usize, Dir4), u32>;
+    type PQE = PqElement<State<D>, u32>;
 
     fn neighbours(
         p: Self::Point,
-        _: Self::Bounds,
+        rules: Self::Bounds,
     ) -> impl Iterator<Item = (Self::Point, Self::Dist)> {
-        neighbours(p).into_iter()
+        neighbours_reverse(p, &rules).into_iter()
     }
 
     fn is_impossible(&self, p: Self::Point) -> bool {
-        self.0[p.1][p.0][dir_to_usize(p.2)].val == b'#'
+        self.0[p.1][p.0][index(p.2)].val == b'#'
     }
 
     fn dist(&self, p: Self::Point) -> Option<Self::Dist> {
-        self.0[p.1][p.0][dir_to_usize(p.2)].distance
+        self.0[p.1][p.0][index(p.2)].end_distance
     }
 
     fn set_dist(&mut self, p: Self::Point, dist: Option<Self::Dist>) {
-        self.0[p.1][p.0][dir_to_usize(p.2)].distance = dist;
+        self.0[p.1][p.0][index(p.2)].end_distance = dist;
     }
 }
 
-pub fn run() {
+/// The routes that all cost the same, counted once per distinct sequence of tiles, and the
+/// states of the first one found.
+struct CostClass<D> {
+    cost: u32,
+    routes: usize,
+    example: Vec<State<D>>,
+}
+
+pub fn run(rules: Rules, k: Option<usize>) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 16).unwrap();
-    let mut contents: Vec<Vec<Vec<Node>>> = contents
+
+    if rules.diagonal {
+        solve::<Dir8, _>(&mut writer, &contents, &rules, k);
+    } else {
+        solve::<Dir4, _>(&mut writer, &contents, &rules, k);
+    }
+}
+
+fn solve<D: Heading, W: Write>(
+    writer: &mut BufWriter<W>,
+    contents: &[&str],
+    rules: &Rules,
+    k: Option<usize>,
+) {
+    let mut map = parse_map::<D>(contents);
+
+    part1::<D, W>(writer, &mut map, rules);
+    part2::<D, W>(writer, &mut map, rules);
+
+    if let Some(k) = k {
+        print_cheapest_routes::<D>(&mut map, rules, k);
+    }
+}
+
+/// One node per heading for each tile.
+fn parse_map<D: Heading>(contents: &[&str]) -> Vec<Vec<Vec<Node>>> {
+    contents
         .iter()
         .map(|line| {
             let by = line.as_bytes();
-            by.iter().map(|b| vec![Node::new(*b); 4]).collect()
+            by.iter()
+                .map(|b| vec![Node::new(*b); D::ALL.len()])
+                .collect()
         })
-        .collect();
+        .collect()
+}
 
-    part1(&mut writer, &mut contents);
-    part2(&mut writer, &mut contents);
+fn part1<D: Heading, W: Write>(
+    writer: &mut BufWriter<W>,
+    map: &mut Vec<Vec<Vec<Node>>>,
+    rules: &Rules,
+) {
+    let start = find_start_end(map, b'S');
+    let mut map = Map(map, PhantomData);
+    map.dijkstra((start.0, start.1, rules.start_heading::<D>()), 0, *rules);
+
+    let (_, best) = end_states::<D>(map.0, rules);
+    printwriteln!(writer, "part 1: {}", best).unwrap();
 }
 
-fn part1<W: Write>(writer: &mut BufWriter<W>, map: &mut Vec<Vec<Vec<Node>>>) {
+fn part2<D: Heading, W: Write>(
+    writer: &mut BufWriter<W>,
+    map: &mut Vec<Vec<Vec<Node>>>,
+    rules: &Rules,
+) {
     let start = find_start_end(map, b'S');
     let end = find_start_end(map, b'E');
-    let mut map = Map(map);
-    map.dijkstra((start.0, start.1, Dir4::Right), 0, ());
-    printwriteln!(
-        writer,
-        "part 1: {}",
-        map.0[end.1][end.0][dir_to_usize(Dir4::Right)]
-            .distance
-            .unwrap()
-    )
-    .unwrap();
-}
-
-fn part2<W: Write>(writer: &mut BufWriter<W>, map: &mut Vec<Vec<Vec<Node>>>) {
-    let mut hs: HashSet<(usize, usize)> = HashSet::new();
+    let start_state = (start.0, start.1, rules.start_heading::<D>());
+    let (end_states, _) = end_states::<D>(map, rules);
+
+    let mut travel: HashMap<(usize, usize), Vec<D>> = HashMap::new();
+    travel.insert(start, Vec::new());
+    travel.insert(end, Vec::new());
+
+    let mut path_counts = HashMap::new();
+    let mut best_paths = 0;
+    for end_state in end_states {
+        compute_cells_on_path_rec(map, end_state, rules, &mut travel);
+        best_paths += count_best_paths(map, end_state, start_state, rules, &mut path_counts);
+    }
+
+    for (y, row) in map.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let c = match travel.get(&(x, y)).map(|headings| headings.as_slice()) {
+                Some([h]) if cell[0].val == b'.' => arrow(*h),
+                Some(_) if cell[0].val == b'.' => b'O',
+                _ => cell[0].val,
+            };
+            print!("{}", u8_to_string(c));
+        }
+        println!();
+    }
+    println!("{} best paths", best_paths);
+
+    printwriteln!(writer, "part 2: {}", travel.len()).unwrap();
+}
+
+/// The end states that satisfy the rules and are reached at the lowest cost, along with that
+/// cost.
+fn end_states<D: Heading>(map: &[Vec<Vec<Node>>], rules: &Rules) -> (Vec<State<D>>, u32) {
     let end = find_start_end(map, b'E');
-    hs.insert(find_start_end(map, b'S'));
-    hs.insert(end);
-    compute_cells_on_path_rec(map, (end.0, end.1, Dir4::Right), &mut hs);
+    let reached: Vec<(State<D>, u32)> = rules
+        .end_headings()
+        .into_iter()
+        .filter_map(|h| {
+            map[end.1][end.0][index(h)]
+                .distance
+                .map(|d| ((end.0, end.1, h), d))
+        })
+        .collect();
+
+    let best = reached
+        .iter()
+        .map(|&(_, d)| d)
+        .min()
+        .expect("end not reachable");
+    let states = reached
+        .into_iter()
+        .filter(|&(_, d)| d == best)
+        .map(|(s, _)| s)
+        .collect();
 
-    printwriteln!(writer, "part 2: {}", hs.len()).unwrap();
+    (states, best)
 }
 
-fn compute_cells_on_path_rec(
+/// Walks backwards from `loc` through every state on a best path, recording for each tile the
+/// headings the reindeer steps out of it in.
+fn compute_cells_on_path_rec<D: Heading>(
     map: &mut Vec<Vec<Vec<Node>>>,
-    loc: (usize, usize, Dir4),
-    hs: &mut HashSet<(usize, usize)>,
+    loc: State<D>,
+    rules: &Rules,
+    travel: &mut HashMap<(usize, usize), Vec<D>>,
 ) {
-    if map[loc.1][loc.0][dir_to_usize(loc.2)].visited {
+    if map[loc.1][loc.0][index(loc.2)].visited {
         return;
     }
 
-    map[loc.1][loc.0][dir_to_usize(loc.2)].visited = true;
+    map[loc.1][loc.0][index(loc.2)].visited = true;
+
+    for n in best_predecessors(map, loc, rules) {
+        let headings = travel.entry((n.0, n.1)).or_default();
+        if (n.0, n.1) != (loc.0, loc.1) && !headings.contains(&n.2) {
+            headings.push(n.2);
+        }
+        compute_cells_on_path_rec(map, n, rules, travel);
+    }
+}
+
+/// Counts the distinct best paths from `start` to `loc`.
+fn count_best_paths<D: Heading>(
+    map: &[Vec<Vec<Node>>],
+    loc: State<D>,
+    start: State<D>,
+    rules: &Rules,
+    path_counts: &mut HashMap<(usize, usize, usize), u128>,
+) -> u128 {
+    if loc == start {
+        return 1;
+    }
+
+    let key = (loc.0, loc.1, index(loc.2));
+    if let Some(&count) = path_counts.get(&key) {
+        return count;
+    }
+
+    let count = best_predecessors(map, loc, rules)
+        .into_iter()
+        .map(|n| count_best_paths(map, n, start, rules, path_counts))
+        .sum();
+    path_counts.insert(key, count);
 
-    for n in neighbours_reverse(loc) {
-        let nval = map[n.0 .1][n.0 .0][dir_to_usize(n.0 .2)];
-        if nval.distance.is_none() {
+    count
+}
+
+/// The states that lead into `loc` along a best path.
+fn best_predecessors<D: Heading>(
+    map: &[Vec<Vec<Node>>],
+    loc: State<D>,
+    rules: &Rules,
+) -> Vec<State<D>> {
+    let Some(dist) = map[loc.1][loc.0][index(loc.2)].distance else {
+        return Vec::new();
+    };
+
+    neighbours_reverse(loc, rules)
+        .into_iter()
+        .filter(|&(n, cost)| {
+            let nval = map[n.1][n.0][index(n.2)];
+            nval.val != b'#' && nval.distance.is_some_and(|d| d + cost == dist)
+        })
+        .map(|(n, _)| n)
+        .collect()
+}
+
+fn print_cheapest_routes<D: Heading>(map: &mut [Vec<Vec<Node>>], rules: &Rules, k: usize) {
+    let start = find_start_end(map, b'S');
+
+    find_end_distances::<D>(map, rules);
+
+    let start_state = (start.0, start.1, rules.start_heading::<D>());
+    let classes = cheapest_routes(map, start_state, rules, k);
+    let best = classes.first().map_or(0, |c| c.cost);
+    for class in &classes {
+        let turns = class
+            .example
+            .windows(2)
+            .filter(|w| (w[0].0, w[0].1) == (w[1].0, w[1].1))
+            .count();
+        let steps = class.example.len() - 1 - turns;
+        let compared = if class.cost == best {
+            "best".into()
+        } else {
+            format!("+{}", class.cost - best)
+        };
+        println!(
+            "cost {} ({}): {} routes, e.g. {} turns, {} steps",
+            class.cost, compared, class.routes, turns, steps
+        );
+    }
+}
+
+/// Sets each node's distance to the nearest acceptable end state, with one backwards search per
+/// end heading.
+fn find_end_distances<D: Heading>(map: &mut [Vec<Vec<Node>>], rules: &Rules) {
+    let end = find_start_end(map, b'E');
+    for h in rules.end_headings::<D>() {
+        let mut reverse_map = map.to_owned();
+        ReverseMap(&mut reverse_map, PhantomData).dijkstra((end.0, end.1, h), 0, *rules);
+        for (row, reverse_row) in map.iter_mut().zip(reverse_map) {
+            for (cell, reverse_cell) in row.iter_mut().zip(reverse_row) {
+                for (node, reverse_node) in cell.iter_mut().zip(reverse_cell) {
+                    node.end_distance = match (node.end_distance, reverse_node.end_distance) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Groups the routes that never revisit a tile by cost, for the `k` cheapest costs. The
+/// reindeer can still turn on the spot, but routes that only differ in which way it turns pass
+/// through the same tiles, so they are counted once. Partial routes are expanded best-first
+/// using their cost so far plus the distance to the end ignoring the tiles already visited,
+/// which is never too much, so complete routes come off the queue cheapest first.
+fn cheapest_routes<D: Heading>(
+    map: &[Vec<Vec<Node>>],
+    start: State<D>,
+    rules: &Rules,
+    k: usize,
+) -> Vec<CostClass<D>> {
+    let end = find_start_end(map, b'E');
+    let end_headings = rules.end_headings();
+    let end_distance = |s: State<D>| map[s.1][s.0][index(s.2)].end_distance;
+
+    // each partial route is its last state, the index of its previous state, and its cost so far
+    let mut arena: Vec<(State<D>, Option<usize>, u32)> = Vec::new();
+    let mut pq = BinaryHeap::new();
+    if let Some(h) = end_distance(start) {
+        arena.push((start, None, 0));
+        pq.push(Reverse((h, Reverse(0), 0)));
+    }
+
+    let mut classes: Vec<CostClass<D>> = Vec::with_capacity(k);
+    let mut seen_tiles = HashSet::new();
+    while let Some(Reverse((_, Reverse(cost), i))) = pq.pop() {
+        let (state, _, _) = arena[i];
+        if (state.0, state.1) == end && end_headings.contains(&state.2) {
+            let mut states = vec![state];
+            let mut prev = arena[i].1;
+            while let Some(p) = prev {
+                states.push(arena[p].0);
+                prev = arena[p].1;
+            }
+            states.reverse();
+
+            let mut tiles: Vec<(usize, usize)> = states.iter().map(|s| (s.0, s.1)).collect();
+            tiles.dedup();
+            if !seen_tiles.insert(tiles) {
+                continue;
+            }
+
+            if let Some(class) = classes.last_mut().filter(|c| c.cost == cost) {
+                class.routes += 1;
+            } else if classes.len() == k {
+                break;
+            } else {
+                classes.push(CostClass {
+                    cost,
+                    routes: 1,
+                    example: states,
+                });
+            }
             continue;
         }
 
-        if map[n.0 .1][n.0 .0][dir_to_usize(n.0 .2)].distance.unwrap()
-            < map[loc.1][loc.0][dir_to_usize(loc.2)].distance.unwrap()
-        {
-            hs.insert((n.0 .0, n.0 .1));
-            compute_cells_on_path_rec(map, n.0, hs);
+        for (n, c) in neighbours(state, rules) {
+            if map[n.1][n.0][index(n.2)].val == b'#' {
+                continue;
+            }
+            let Some(h) = end_distance(n) else {
+                continue;
+            };
+
+            // a step can't go back onto a tile the route has already been on, and a turn can't
+            // come back round to a heading it has already faced on this tile
+            let stepped = (n.0, n.1) != (state.0, state.1);
+            let mut prev = Some(i);
+            let mut revisit = false;
+            while let Some(p) = prev {
+                let s = arena[p].0;
+                if (stepped && (s.0, s.1) == (n.0, n.1)) || s == n {
+                    revisit = true;
+                    break;
+                }
+                prev = arena[p].1;
+            }
+            if revisit {
+                continue;
+            }
+
+            arena.push((n, Some(i), cost + c));
+            pq.push(Reverse((cost + c + h, Reverse(cost + c), arena.len() - 1)));
         }
     }
+
+    classes
+}
+
+/// Turns a facing such as "up" or "down-left" into an (x, y) delta.
+fn parse_facing(s: &str, diagonal: bool) -> (i64, i64) {
+    match s {
+        "up" => (0, -1),
+        "right" => (1, 0),
+        "down" => (0, 1),
+        "left" => (-1, 0),
+        "up-right" if diagonal => (1, -1),
+        "down-right" if diagonal => (1, 1),
+        "down-left" if diagonal => (-1, 1),
+        "up-left" if diagonal => (-1, -1),
+        _ => panic!("invalid facing: {}", s),
+    }
+}
+
+fn heading<D: Heading>(delta: (i64, i64)) -> D {
+    *D::ALL.iter().find(|h| h.delta() == delta).unwrap()
 }
 
-fn dir_to_usize(dir: Dir4) -> usize {
-    match dir {
-        Dir4::Up => 3,
-        Dir4::Down => 1,
-        Dir4::Left => 2,
-        Dir4::Right => 0,
+fn index<D: Heading>(h: D) -> usize {
+    D::ALL.iter().position(|&d| d == h).unwrap()
+}
+
+fn arrow<D: Heading>(h: D) -> u8 {
+    match h.delta() {
+        (0, -1) => b'^',
+        (1, 0) => b'>',
+        (0, 1) => b'v',
+        (-1, 0) => b'<',
+        (1, -1) | (-1, 1) => b'/',
+        _ => b'\\',
     }
 }
 
-fn neighbours(p: (usize, usize, Dir4)) -> Vec<((usize, usize, Dir4), u32)> {
+fn neighbours<D: Heading>(p: State<D>, rules: &Rules) -> Vec<(State<D>, u32)> {
     let straight = p.2.apply_delta_to_usizes((p.0, p.1));
 
-    vec![
-        ((p.0, p.1, p.2.rotate_right()), 1000),
-        ((p.0, p.1, p.2.rotate_left()), 1000),
-        ((straight.0, straight.1, p.2), 1),
-    ]
+    let mut ns = vec![
+        ((p.0, p.1, p.2.rotate_right()), rules.turn_cost),
+        ((p.0, p.1, p.2.rotate_left()), rules.turn_cost),
+        ((straight.0, straight.1, p.2), rules.step_cost),
+    ];
+    if let Some(cost) = rules.u_turn_cost {
+        ns.push(((p.0, p.1, p.2.opposite()), cost));
+    }
+
+    ns
 }
 
-fn neighbours_reverse(p: (usize, usize, Dir4)) -> Vec<((usize, usize, Dir4), u32)> {
-    let mut n = neighbours(p);
-    (n[2].0 .0, n[2].0 .1) = p.2.opposite().apply_delta_to_usizes((p.0, p.1));
+fn neighbours_reverse<D: Heading>(p: State<D>, rules: &Rules) -> Vec<(State<D>, u32)> {
+    let mut ns = neighbours(p, rules);
+    (ns[2].0 .0, ns[2].0 .1) = p.2.opposite().apply_delta_to_usizes((p.0, p.1));
 
-    n
+    ns
 }
 
 fn find_start_end(map: &[Vec<Vec<Node>>], symbol: u8) -> (usize, usize) {
@@ -157,3 +558,59 @@ fn find_start_end(map: &[Vec<Vec<Node>>], symbol: u8) -> (usize, usize) {
 
     panic!("start not found");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 15] = [
+        "###############",
+        "#.......#....E#",
+        "#.#.###.#.###.#",
+        "#.....#.#...#.#",
+        "#.###.#####.#.#",
+        "#.#.#.......#.#",
+        "#.#.#####.###.#",
+        "#...........#.#",
+        "###.#.#####.#.#",
+        "#...#.....#.#.#",
+        "#.#.#.###.#.#.#",
+        "#.....#...#.#.#",
+        "#.###.#.#.#.#.#",
+        "#S..#.....#...#",
+        "###############",
+    ];
+
+    fn example_routes(u_turn_cost: Option<u32>, k: usize) -> Vec<CostClass<Dir4>> {
+        let rules = Rules::new(1, 1000, u_turn_cost, false, "right", None);
+        let mut map = parse_map::<Dir4>(&EXAMPLE);
+        find_end_distances::<Dir4>(&mut map, &rules);
+
+        let start = find_start_end(&map, b'S');
+        cheapest_routes(&map, (start.0, start.1, Dir4::Right), &rules, k)
+    }
+
+    fn tiles(states: &[State<Dir4>]) -> Vec<(usize, usize)> {
+        let mut tiles: Vec<(usize, usize)> = states.iter().map(|s| (s.0, s.1)).collect();
+        tiles.dedup();
+
+        tiles
+    }
+
+    #[test]
+    fn second_best_is_a_different_path() {
+        for u_turn_cost in [None, Some(500)] {
+            let classes = example_routes(u_turn_cost, 2);
+            let summary: Vec<(u32, usize)> = classes.iter().map(|c| (c.cost, c.routes)).collect();
+            assert_eq!(summary, [(7036, 3), (9040, 1)]);
+
+            // no stepping out and back: every tile is visited once
+            let second = tiles(&classes[1].example);
+            let mut distinct = second.clone();
+            distinct.sort();
+            distinct.dedup();
+            assert_eq!(distinct.len(), second.len());
+            assert_ne!(second, tiles(&classes[0].example));
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::HashSet,
     io::{BufWriter, Write},
     iter,
 };
@@ -10,8 +10,16 @@ use aoclib_rs::{
     prep_io, printwriteln, split_and_parse, u8_to_string,
 };
 
-const WIDTH: usize = 71;
-const HEIGHT: usize = 71;
+// When --bytes isn't given, part 1 drops as many bytes as the puzzle does: the example is a 7x7
+// grid that looks at the first 12 bytes, and the real input is a 71x71 grid that looks at the
+// first 1024. Any grid no bigger than the example's is taken to be the example.
+const EXAMPLE_SIZE: usize = 7;
+const EXAMPLE_BYTES: usize = 12;
+const BYTES: usize = 1024;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
 
 #[derive(Copy, Clone)]
 struct Node {
@@ -56,7 +64,7 @@ impl Dijkstrable for Map {
     }
 }
 
-pub fn run() {
+pub fn run(width: Option<usize>, height: Option<usize>, bytes: Option<usize>) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 18).unwrap();
     let contents: Vec<Vec<usize>> = contents
@@ -64,71 +72,207 @@ pub fn run() {
         .map(|line| split_and_parse(line, ",").unwrap())
         .collect();
 
-    part1(&mut writer, &contents[..1024]);
-    part2(&mut writer, &contents);
+    let width = width.unwrap_or_else(|| contents.iter().map(|p| p[0]).max().unwrap() + 1);
+    let height = height.unwrap_or_else(|| contents.iter().map(|p| p[1]).max().unwrap() + 1);
+    let bytes = bytes.unwrap_or(if width <= EXAMPLE_SIZE && height <= EXAMPLE_SIZE {
+        EXAMPLE_BYTES
+    } else {
+        BYTES
+    });
+    assert!(
+        bytes <= contents.len(),
+        "asked for {} bytes but the input only lists {}",
+        bytes,
+        contents.len()
+    );
+    println!("{}x{} grid, {} bytes", width, height, bytes);
+
+    part1(&mut writer, &contents[..bytes], (width, height));
+    part2(&mut writer, &contents, (width, height));
 }
 
-fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &[Vec<usize>]) {
-    let mut mp = vec![vec![Node::new(); WIDTH]; HEIGHT];
-    let mut map = HashMap::new();
+fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &[Vec<usize>], bounds: (usize, usize)) {
+    let (width, height) = bounds;
+    let mut mp = vec![vec![Node::new(); width]; height];
     for point in contents {
-        map.insert((point[0], point[1]), b'#');
         mp[point[1]][point[0]].val = b'#';
     }
     mp[0][0].distance = Some(0);
 
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            print!("{}", u8_to_string(*map.entry((x, y)).or_insert(b'.')));
+    let mut mp = Map(mp);
+    mp.dijkstra((0, 0), 0, bounds);
+
+    let path: HashSet<(usize, usize)> = shortest_path(&mp, (width - 1, height - 1), bounds)
+        .into_iter()
+        .collect();
+    print_grid(&mp.0, |x, y, node| {
+        if path.contains(&(x, y)) {
+            "O".into()
+        } else {
+            u8_to_string(node.val)
         }
-        println!();
-    }
+    });
 
-    let mut mp = Map(mp);
-    mp.dijkstra((0, 0), 0, (WIDTH, HEIGHT));
     printwriteln!(
         writer,
         "part 1: {}",
-        mp.0[HEIGHT - 1][WIDTH - 1].distance.unwrap()
+        mp.0[height - 1][width - 1].distance.unwrap()
     )
     .unwrap();
 }
 
-fn part2<W: Write>(writer: &mut BufWriter<W>, contents: &[Vec<usize>]) {
-    let mut mp = vec![vec![Node::new(); WIDTH]; HEIGHT];
-    let mut map = HashMap::new();
-    for point in contents[..=1024].iter() {
-        map.insert((point[0], point[1]), b'#');
+fn part2<W: Write>(writer: &mut BufWriter<W>, contents: &[Vec<usize>], bounds: (usize, usize)) {
+    let (width, height) = bounds;
+    let curr = first_blocking_byte(contents, bounds).expect("exit is never cut off");
+
+    let mut mp = vec![vec![Node::new(); width]; height];
+    for point in contents[..=curr].iter() {
         mp[point[1]][point[0]].val = b'#';
     }
-    mp[0][0].distance = Some(0);
 
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            print!("{}", u8_to_string(*map.entry((x, y)).or_insert(b'.')));
+    // what's still reachable from the start once the blocking byte has fallen
+    let mut mp = Map(mp);
+    if mp.0[0][0].val != b'#' {
+        mp.0[0][0].distance = Some(0);
+        mp.dijkstra((0, 0), 0, bounds);
+    }
+
+    let point = &contents[curr];
+    print_grid(&mp.0, |x, y, node| {
+        if (x, y) == (point[0], point[1]) {
+            format!("{}#{}", RED, RESET)
+        } else if node.distance.is_some() {
+            format!("{}o{}", GREEN, RESET)
+        } else {
+            u8_to_string(node.val)
+        }
+    });
+
+    printwriteln!(writer, "part 2: {} ({},{})", curr, point[0], point[1]).unwrap();
+}
+
+/// Walks back from `end` along decreasing distances to recover one shortest path from the
+/// start, returned in order from the start.
+fn shortest_path(mp: &Map, end: (usize, usize), bounds: (usize, usize)) -> Vec<(usize, usize)> {
+    let mut path = Vec::new();
+    let mut curr = end;
+    let Some(mut dist) = mp.0[end.1][end.0].distance else {
+        return path;
+    };
+
+    path.push(curr);
+    while dist > 0 {
+        curr = Dir4::iter_valid_usizes_deltas(curr, bounds)
+            .find(|&(x, y)| mp.0[y][x].distance == Some(dist - 1))
+            .unwrap();
+        dist -= 1;
+        path.push(curr);
+    }
+
+    path.reverse();
+    path
+}
+
+fn print_grid<F: Fn(usize, usize, &Node) -> String>(mp: &[Vec<Node>], cell_to_string: F) {
+    for (y, row) in mp.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            print!("{}", cell_to_string(x, y, cell));
         }
         println!();
     }
+}
+
+/// Finds the index of the first byte that cuts the exit off from the start. All the bytes are
+/// dropped up front, then removed again in reverse order, joining each freed cell to its open
+/// neighbours, until the start and the exit end up in the same set.
+fn first_blocking_byte(contents: &[Vec<usize>], bounds: (usize, usize)) -> Option<usize> {
+    let (width, height) = bounds;
+    let index = |(x, y): (usize, usize)| y * width + x;
+
+    // count rather than flag walls, in case the same cell is hit more than once
+    let mut walls = vec![vec![0; width]; height];
+    for point in contents {
+        walls[point[1]][point[0]] += 1;
+    }
 
-    let mut curr = 1024;
-    loop {
-        let mut mp2 = Map(mp.clone());
-        mp2.dijkstra((0, 0), 0, (WIDTH, HEIGHT));
-        if mp2.0[HEIGHT - 1][WIDTH - 1].distance.is_none() {
-            break;
+    let mut sets = UnionFind::new(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            if walls[y][x] > 0 {
+                continue;
+            }
+            for (nx, ny) in Dir4::iter_valid_usizes_deltas((x, y), bounds) {
+                if walls[ny][nx] == 0 {
+                    sets.union(index((x, y)), index((nx, ny)));
+                }
+            }
         }
-        curr += 1;
-        let point = &contents[curr];
-        mp[point[1]][point[0]].val = b'#';
     }
 
-    for row in mp {
-        for cell in row {
-            print!("{}", u8_to_string(cell.val));
+    let (start, end) = (index((0, 0)), index((width - 1, height - 1)));
+    if sets.find(start) == sets.find(end) {
+        return None;
+    }
+
+    for (i, point) in contents.iter().enumerate().rev() {
+        let (x, y) = (point[0], point[1]);
+        walls[y][x] -= 1;
+        if walls[y][x] > 0 {
+            continue;
+        }
+
+        for (nx, ny) in Dir4::iter_valid_usizes_deltas((x, y), bounds) {
+            if walls[ny][nx] == 0 {
+                sets.union(index((x, y)), index((nx, ny)));
+            }
+        }
+
+        if sets.find(start) == sets.find(end) {
+            return Some(i);
         }
-        println!();
     }
 
-    let point = &contents[curr];
-    printwriteln!(writer, "part 2: {} ({},{})", curr, point[0], point[1]).unwrap();
+    None
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+
+        while self.parent[i] != root {
+            let next = self.parent[i];
+            self.parent[i] = root;
+            i = next;
+        }
+
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        if self.size[a] < self.size[b] {
+            (a, b) = (b, a);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
 }
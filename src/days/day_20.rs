@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     fmt,
     io::{BufWriter, Write},
     iter,
@@ -61,35 +61,7 @@ impl Dijkstrable for Points<'_> {
     }
 }
 
-struct PointsPart2<'a>(&'a mut Vec<Vec<Point>>);
-
-impl Dijkstrable for PointsPart2<'_> {
-    type Point = (usize, usize);
-    type Bounds = (usize, usize);
-    type Dist = u32;
-    type PQE = PqElement<(usize, usize), u32>;
-
-    fn neighbours(
-        p: Self::Point,
-        b: Self::Bounds,
-    ) -> impl Iterator<Item = (Self::Point, Self::Dist)> {
-        iter::zip(Dir4::iter_valid_usizes_deltas(p, b), iter::repeat(1))
-    }
-
-    fn is_impossible(&self, _: Self::Point) -> bool {
-        false
-    }
-
-    fn dist(&self, p: Self::Point) -> Option<Self::Dist> {
-        self.0[p.1][p.0].dist
-    }
-
-    fn set_dist(&mut self, p: Self::Point, dist: Option<Self::Dist>) {
-        self.0[p.1][p.0].dist = dist;
-    }
-}
-
-pub fn run() {
+pub fn run(cheat_len: i32, min_save: i32) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 20).unwrap();
     let contents: Vec<&[u8]> = contents.iter().map(|s| s.as_bytes()).collect();
@@ -102,7 +74,6 @@ pub fn run() {
         }
         points.push(points_row);
     }
-    let points_copy = points.clone();
 
     let end = position_2d(&points, |cell: &Point| cell.val == b'E').unwrap();
     points[end.1][end.0].dist = Some(0);
@@ -112,115 +83,89 @@ pub fn run() {
 
     println!("{:?}", points);
 
-    part1(&mut writer, &points);
-    part2(&mut writer, &points, &points_copy);
+    part1(&mut writer, &points, min_save);
+    part2(&mut writer, &points, cheat_len, min_save);
 }
 
-fn part1<W: Write>(writer: &mut BufWriter<W>, points: &[Vec<Point>]) {
-    let mut cheats = HashMap::new();
-    let mut over100 = 0;
-    for (y, row) in points.iter().enumerate() {
-        for (x, cell) in row.iter().enumerate() {
-            if cell.val == b'#' || cell.dist.is_none() {
-                continue;
-            }
+fn part1<W: Write>(writer: &mut BufWriter<W>, points: &[Vec<Point>], min_save: i32) {
+    let cheats = compute_cheats(points, 2);
+    print_histogram(&cheats, min_save);
 
-            for n1 in Dir4::iter_valid_usizes_deltas((x, y), (points.len(), row.len())) {
-                for n2 in Dir4::iter_valid_usizes_deltas((n1.0, n1.1), (points.len(), row.len())) {
-                    let n2cell = points[n2.1][n2.0];
-                    if n2cell.val != b'#' && n2cell.dist.is_some() {
-                        let dist = cell.dist.unwrap() as i32 - n2cell.dist.unwrap() as i32 - 2;
-                        if dist > 0 {
-                            println!("({}, {}) -> ({}, {}) saves {}", x, y, n2.0, n2.1, dist);
-                            cheats.entry(dist).and_modify(|i| *i += 1).or_insert(1);
-
-                            if dist >= 100 {
-                                over100 += 1;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    for (dist, count) in cheats {
-        println!("{} cheats that save {}", count, dist);
-    }
-
-    printwriteln!(writer, "part 1: {}", over100).unwrap();
+    printwriteln!(writer, "part 1: {}", count_at_least(&cheats, min_save)).unwrap();
 }
 
 fn part2<W: Write>(
     writer: &mut BufWriter<W>,
     points: &[Vec<Point>],
-    original_points: &[Vec<Point>],
+    cheat_len: i32,
+    min_save: i32,
 ) {
-    let mut cheats = HashMap::new();
-    for (y, row) in points.iter().enumerate() {
-        for (x, cell) in row.iter().enumerate() {
-            if cell.val == b'#' || cell.dist.is_none() {
-                continue;
-            }
+    let cheats = compute_cheats(points, cheat_len);
+    print_histogram(&cheats, min_save);
 
-            println!("{} {}", x, y);
-            let inner_cheats = compute_cheats_20(points, original_points.to_owned(), x, y);
-            merge_hash_maps(&mut cheats, &inner_cheats);
-        }
-    }
+    printwriteln!(writer, "part 2: {}", count_at_least(&cheats, min_save)).unwrap();
+}
 
-    let mut over100 = 0;
-    for (dist, count) in cheats {
-        if dist < 100 {
-            continue;
-        }
+fn count_at_least(cheats: &BTreeMap<i32, u32>, min_save: i32) -> u32 {
+    cheats.range(min_save..).map(|(_, count)| count).sum()
+}
 
-        over100 += count;
-        println!("{} cheats that save {}", count, dist);
+/// Prints how many cheats save each amount of time, for savings of at least `min_save`.
+fn print_histogram(cheats: &BTreeMap<i32, u32>, min_save: i32) {
+    println!("{:>6} | {:>6}", "saves", "cheats");
+    println!("{:-<7}+{:-<7}", "", "");
+    for (dist, count) in cheats.range(min_save..) {
+        println!("{:>6} | {:>6}", dist, count);
     }
-
-    printwriteln!(writer, "part 2: {}", over100).unwrap();
 }
 
-fn compute_cheats_20(
-    points: &[Vec<Point>],
-    mut points_part_2: Vec<Vec<Point>>,
-    start_x: usize,
-    start_y: usize,
-) -> HashMap<i32, u32> {
-    let bounds = (points_part_2.len(), points_part_2[0].len());
-    PointsPart2(&mut points_part_2).dijkstra((start_x, start_y), 0, bounds);
-
-    let mut cheats = HashMap::new();
-    for (y, row) in points_part_2.iter().enumerate() {
+/// Counts cheats by the time they save. Cheats ignore walls, so every track cell within
+/// Manhattan distance `radius` of a track cell is a possible cheat destination, and the time
+/// saved follows directly from the two cells' distances to the end.
+fn compute_cheats(points: &[Vec<Point>], radius: i32) -> BTreeMap<i32, u32> {
+    let mut cheats = BTreeMap::new();
+    for (y, row) in points.iter().enumerate() {
         for (x, cell) in row.iter().enumerate() {
-            if points[y][x].val == b'#' || points[y][x].dist.is_none() {
-                continue;
-            }
-
-            if cell.dist.is_none() || cell.dist.unwrap() > 20 {
-                continue;
-            }
-
-            let dist_saved = points[start_y][start_x].dist.unwrap() as i32
-                - points[y][x].dist.unwrap() as i32
-                - cell.dist.unwrap() as i32;
-            if dist_saved <= 0 {
+            let Some(start_dist) = track_dist(cell) else {
                 continue;
+            };
+
+            for dy in -radius..=radius {
+                let reach = radius - dy.abs();
+                for dx in -reach..=reach {
+                    let (Some(cx), Some(cy)) = (
+                        x.checked_add_signed(dx as isize),
+                        y.checked_add_signed(dy as isize),
+                    ) else {
+                        continue;
+                    };
+                    let Some(end_dist) = points
+                        .get(cy)
+                        .and_then(|row| row.get(cx))
+                        .and_then(track_dist)
+                    else {
+                        continue;
+                    };
+
+                    let dist_saved = start_dist as i32 - end_dist as i32 - dx.abs() - dy.abs();
+                    if dist_saved > 0 {
+                        cheats
+                            .entry(dist_saved)
+                            .and_modify(|i| *i += 1)
+                            .or_insert(1);
+                    }
+                }
             }
-
-            cheats
-                .entry(dist_saved)
-                .and_modify(|i| *i += 1)
-                .or_insert(1);
         }
     }
 
     cheats
 }
 
-fn merge_hash_maps(cheats: &mut HashMap<i32, u32>, other: &HashMap<i32, u32>) {
-    for (k, v) in other {
-        cheats.entry(*k).and_modify(|i| *i += *v).or_insert(*v);
+fn track_dist(cell: &Point) -> Option<u32> {
+    if cell.val == b'#' {
+        None
+    } else {
+        cell.dist
     }
 }
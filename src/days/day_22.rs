@@ -1,7 +1,8 @@
 use std::{
-    cmp::max,
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
+    collections::HashMap,
     io::{BufWriter, Write},
+    thread,
 };
 
 use aoclib_rs::{prep_io, printwriteln};
@@ -10,66 +11,186 @@ const MUL1: u64 = 64;
 const MUL2: u64 = 2_048;
 const DIV: u64 = 32;
 const MOD: u64 = 16_777_216;
+const BITS: usize = 24;
 
-pub fn run() {
+const STEPS: usize = 2_000;
+
+// buyers whose secrets are advanced together, so the compiler can vectorise each step
+const LANES: usize = 16;
+
+// each price change is in -9..=9, so a window of four changes is a 4-digit base-19 number
+const WINDOWS: usize = 19 * 19 * 19 * 19;
+
+/// A BITSxBITS matrix over GF(2), stored as its columns. Each step of the secret generation
+/// only shifts, xors and truncates, so it is linear over GF(2) and can be written as one of
+/// these, and N steps as its Nth power.
+#[derive(Copy, Clone)]
+struct BitMatrix([u64; BITS]);
+
+impl BitMatrix {
+    fn identity() -> BitMatrix {
+        let mut columns = [0; BITS];
+        for (j, column) in columns.iter_mut().enumerate() {
+            *column = 1 << j;
+        }
+
+        BitMatrix(columns)
+    }
+
+    /// The matrix for a single step, read off from where each basis vector goes.
+    fn secret_step() -> BitMatrix {
+        let mut columns = [0; BITS];
+        for (j, column) in columns.iter_mut().enumerate() {
+            *column = get_next_secret(1 << j);
+        }
+
+        BitMatrix(columns)
+    }
+
+    fn apply(&self, v: u64) -> u64 {
+        let mut out = 0;
+        for (j, column) in self.0.iter().enumerate() {
+            if v & (1 << j) != 0 {
+                out ^= column;
+            }
+        }
+
+        out
+    }
+
+    fn mul(&self, other: &BitMatrix) -> BitMatrix {
+        let mut columns = [0; BITS];
+        for (column, other_column) in columns.iter_mut().zip(other.0) {
+            *column = self.apply(other_column);
+        }
+
+        BitMatrix(columns)
+    }
+
+    fn pow(&self, mut n: u64) -> BitMatrix {
+        let mut result = BitMatrix::identity();
+        let mut base = *self;
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.mul(&base);
+            n >>= 1;
+        }
+
+        result
+    }
+}
+
+pub fn run(top: Option<usize>, secret_steps: Option<u64>, cycle_length: bool) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 22).unwrap();
     let contents: Vec<u64> = contents.iter().map(|n| n.parse().unwrap()).collect();
 
-    let (prices, price_changes) = part1(&mut writer, &contents);
-    part2(&mut writer, &prices, &price_changes);
+    let prices = part1(&mut writer, &contents);
+    part2(&mut writer, &contents, &prices, top);
+
+    let step = BitMatrix::secret_step();
+    if let Some(n) = secret_steps {
+        let jump = step.pow(n);
+        for seed in &contents {
+            println!("{}: {} after {} steps", seed, jump.apply(*seed), n);
+        }
+    }
+
+    if cycle_length {
+        for seed in &contents {
+            println!("{}: cycle length {}", seed, get_cycle_length(&step, *seed));
+        }
+    }
 }
 
-fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &[u64]) -> (Vec<Vec<u64>>, Vec<Vec<i64>>) {
+fn part1<W: Write>(writer: &mut BufWriter<W>, contents: &[u64]) -> Vec<Vec<u8>> {
     let mut secrets = contents.to_owned();
-    let mut prices = Vec::with_capacity(secrets.len());
-    let mut price_changes = Vec::with_capacity(secrets.len());
-    for (i, s) in &mut secrets.iter_mut().enumerate() {
-        let mut single_prices = Vec::with_capacity(2_000);
-        let mut single_price_changes = Vec::with_capacity(2_000);
-        for _ in 0..2_000 {
-            let prev = *s;
-            *s = get_next_secret(*s);
+    let mut prices = vec![vec![0; STEPS + 1]; secrets.len()];
+    for (lanes, lane_prices) in secrets.chunks_mut(LANES).zip(prices.chunks_mut(LANES)) {
+        let mut block = [0; LANES];
+        block[..lanes.len()].copy_from_slice(lanes);
 
-            let price = *s % 10;
-            single_prices.push(price);
-            single_price_changes.push(price as i64 - (prev % 10) as i64);
+        for step in 0..=STEPS {
+            if step > 0 {
+                get_next_secrets(&mut block);
+            }
+            for (single_prices, s) in lane_prices.iter_mut().zip(block) {
+                single_prices[step] = (s % 10) as u8;
+            }
         }
-        prices.push(single_prices);
-        price_changes.push(single_price_changes);
-        println!("{}: {}", contents[i], s);
+
+        lanes.copy_from_slice(&block[..lanes.len()]);
+    }
+
+    for (seed, s) in contents.iter().zip(&secrets) {
+        println!("{}: {}", seed, s);
     }
 
     printwriteln!(writer, "part 1: {}", secrets.iter().sum::<u64>()).unwrap();
 
-    (prices, price_changes)
+    prices
 }
 
-fn part2<W: Write>(writer: &mut BufWriter<W>, prices: &[Vec<u64>], price_changes: &Vec<Vec<i64>>) {
-    let mut hs = HashSet::new();
-    let mut chunk_indices = Vec::with_capacity(price_changes.len());
-    for pc in price_changes {
-        let indices = overlapping_chunks_and_chunk_indices(pc);
-        for chunk in indices.keys() {
-            hs.insert(chunk.clone());
+fn part2<W: Write>(
+    writer: &mut BufWriter<W>,
+    seeds: &[u64],
+    prices: &[Vec<u8>],
+    top: Option<usize>,
+) {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = prices.len().div_ceil(threads).max(1);
+    let totals = thread::scope(|scope| {
+        let handles: Vec<_> = prices
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| score_windows(chunk)))
+            .collect();
+
+        let mut totals = vec![0; WINDOWS];
+        for handle in handles {
+            for (total, bananas) in totals.iter_mut().zip(handle.join().unwrap()) {
+                *total += bananas;
+            }
         }
-        chunk_indices.push(indices);
+
+        totals
+    });
+
+    let mut ranked: Vec<usize> = (0..WINDOWS).filter(|&w| totals[w] > 0).collect();
+    ranked.sort_by_key(|&w| (Reverse(totals[w]), w));
+
+    // no sequence sells for anything, so there's no best one to report
+    let Some(&best) = ranked.first() else {
+        printwriteln!(writer, "part 2: 0").unwrap();
+        return;
+    };
+    println!("best sequence: {}", window_to_string(best));
+    for (i, seed) in seeds.iter().enumerate() {
+        match first_sale(&prices[i], best) {
+            None => println!("buyer {} (seed {}): never", i, seed),
+            Some(index) => println!(
+                "buyer {} (seed {}): sells at index {} for {}",
+                i,
+                seed,
+                index,
+                prices[i][index + 1]
+            ),
+        };
     }
 
-    let mut m = 0;
-    for (c, chunk) in hs.iter().enumerate() {
-        println!(
-            "about to get_total_bananas() for chunk {} of {}",
-            c,
-            hs.len()
-        );
-        m = max(
-            m,
-            get_total_bananas(prices, price_changes, chunk, &chunk_indices),
-        );
+    if let Some(top) = top {
+        for (rank, &w) in ranked.iter().take(top).enumerate() {
+            println!(
+                "{}. {} - {} bananas",
+                rank + 1,
+                window_to_string(w),
+                totals[w]
+            );
+        }
     }
 
-    printwriteln!(writer, "part 2: {}", m).unwrap();
+    printwriteln!(writer, "part 2: {}", totals[best]).unwrap();
 }
 
 fn get_next_secret(mut s: u64) -> u64 {
@@ -80,31 +201,152 @@ fn get_next_secret(mut s: u64) -> u64 {
     s
 }
 
-fn overlapping_chunks_and_chunk_indices(pc: &[i64]) -> HashMap<Vec<i64>, usize> {
-    let mut hm = HashMap::with_capacity(pc.len());
-    for i in 0..(pc.len() - 3) {
-        let chunk = vec![pc[i], pc[i + 1], pc[i + 2], pc[i + 3]];
-        hm.entry(chunk).or_insert(i + 3);
+fn get_next_secrets(secrets: &mut [u64; LANES]) {
+    for s in secrets {
+        *s = get_next_secret(*s);
+    }
+}
+
+/// The smallest n > 0 for which n steps take `seed` back to itself, found with baby-step
+/// giant-step. The step is invertible, so every seed is on a cycle, and there are only 2^BITS
+/// possible secrets so the cycle is at most that long.
+fn get_cycle_length(step: &BitMatrix, seed: u64) -> u64 {
+    let m = 1 << (BITS / 2);
+
+    // baby steps: step^j(seed) for 0 <= j < m
+    let mut baby = HashMap::with_capacity(m as usize);
+    let mut s = seed;
+    for j in 0..m {
+        if j > 0 && s == seed {
+            return j;
+        }
+        baby.insert(s, j);
+        s = get_next_secret(s);
+    }
+
+    // giant steps: step^(i*m)(seed) = step^j(seed) means step^(i*m - j)(seed) = seed
+    let giant = step.pow(m);
+    let mut s = seed;
+    for i in 1..=(m + 1) {
+        s = giant.apply(s);
+        if let Some(j) = baby.get(&s) {
+            return i * m - j;
+        }
     }
 
-    hm
+    panic!("no cycle found for {}", seed);
 }
 
-fn get_total_bananas(
-    prices: &[Vec<u64>],
-    price_changes: &[Vec<i64>],
-    chunk: &[i64],
-    chunk_indices: &[HashMap<Vec<i64>, usize>],
-) -> u64 {
-    let mut total = 0;
-    for i in 0..price_changes.len() {
-        match chunk_indices[i].get(chunk) {
-            None => (),
-            Some(&index) => {
-                total += prices[i][index];
+/// The windows of four consecutive price changes, as indices into a dense table, paired with
+/// the index of the change that ends each window.
+fn windows(prices: &[u8]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut w = 0;
+    prices.windows(2).enumerate().filter_map(move |(i, p)| {
+        let change = p[1] as usize + 9 - p[0] as usize;
+        w = (w * 19 + change) % WINDOWS;
+        if i >= 3 {
+            Some((w, i))
+        } else {
+            None
+        }
+    })
+}
+
+/// Sums, for every window, the price each buyer sells at the first time it sees that window.
+fn score_windows(prices: &[Vec<u8>]) -> Vec<u32> {
+    let mut totals = vec![0; WINDOWS];
+    let mut seen = vec![0; WINDOWS];
+    for (buyer, single_prices) in prices.iter().enumerate() {
+        // stamp with the buyer number rather than clearing between buyers
+        let stamp = buyer as u32 + 1;
+        for (w, i) in windows(single_prices) {
+            if seen[w] != stamp {
+                seen[w] = stamp;
+                totals[w] += single_prices[i + 1] as u32;
             }
-        };
+        }
+    }
+
+    totals
+}
+
+/// The index of the change at which a buyer first sees the window, if ever.
+fn first_sale(prices: &[u8], window: usize) -> Option<usize> {
+    windows(prices).find(|&(w, _)| w == window).map(|(_, i)| i)
+}
+
+fn window_to_string(mut w: usize) -> String {
+    let mut changes = [0; 4];
+    for c in changes.iter_mut().rev() {
+        *c = (w % 19) as i64 - 9;
+        w /= 19;
     }
 
-    total
+    changes
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iterate(mut s: u64, n: u64) -> u64 {
+        for _ in 0..n {
+            s = get_next_secret(s);
+        }
+
+        s
+    }
+
+    #[test]
+    fn example_secrets() {
+        let mut s = 123;
+        for expected in [15887950, 16495136, 527345, 704524, 1553684] {
+            s = get_next_secret(s);
+            assert_eq!(s, expected);
+        }
+
+        for (seed, expected) in [
+            (1, 8685429),
+            (10, 4700978),
+            (100, 15273692),
+            (2024, 8667524),
+        ] {
+            assert_eq!(iterate(seed, STEPS as u64), expected);
+        }
+    }
+
+    #[test]
+    fn jump_ahead_matches_iteration() {
+        let step = BitMatrix::secret_step();
+        for seed in [0, 1, 10, 123, 2024, MOD - 1] {
+            for n in [0, 1, 2, 3, 10, 64, 1000, 2000] {
+                assert_eq!(
+                    step.pow(n).apply(seed),
+                    iterate(seed, n),
+                    "{} x {}",
+                    seed,
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cycle_length_matches_walk() {
+        let step = BitMatrix::secret_step();
+        for seed in [0, 123] {
+            let mut s = get_next_secret(seed);
+            let mut walked = 1;
+            while s != seed {
+                s = get_next_secret(s);
+                walked += 1;
+            }
+
+            assert_eq!(get_cycle_length(&step, seed), walked, "{}", seed);
+        }
+    }
 }
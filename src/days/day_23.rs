@@ -1,11 +1,15 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
     io::{BufWriter, Write},
+    iter,
     ops::{Deref, DerefMut},
 };
 
 use aoclib_rs::{prep_io, printwriteln};
 
+use regex::Regex;
+
 #[derive(Clone)]
 struct Graph<'a>(HashMap<&'a str, Node<'a>>);
 
@@ -47,7 +51,70 @@ struct Node<'a> {
     neighbours: HashSet<&'a str>,
 }
 
-pub fn run() {
+/// A condition on the computers in a clique.
+enum CliqueFilter {
+    /// Some computer's name starts with this.
+    Prefix(String),
+    /// Some computer's name matches this.
+    Regex(Regex),
+    /// All of these computers are in the clique.
+    Contains(Vec<String>),
+}
+
+impl CliqueFilter {
+    fn matches(&self, clique: &[&str]) -> bool {
+        match self {
+            CliqueFilter::Prefix(prefix) => clique.iter().any(|n| n.starts_with(prefix.as_str())),
+            CliqueFilter::Regex(re) => clique.iter().any(|n| re.is_match(n)),
+            CliqueFilter::Contains(names) => names.iter().all(|n| clique.contains(&n.as_str())),
+        }
+    }
+}
+
+/// Which cliques of size k to look for, and whether to list them or just count them.
+pub struct CliqueQuery {
+    k: usize,
+    filters: Vec<CliqueFilter>,
+    list: bool,
+}
+
+impl CliqueQuery {
+    pub fn new(
+        k: usize,
+        prefix: Option<&str>,
+        name_regex: Option<&str>,
+        contains: &[String],
+        list: bool,
+    ) -> CliqueQuery {
+        let mut filters = Vec::new();
+        if let Some(prefix) = prefix {
+            filters.push(CliqueFilter::Prefix(prefix.to_owned()));
+        }
+        if let Some(name_regex) = name_regex {
+            filters.push(CliqueFilter::Regex(Regex::new(name_regex).unwrap()));
+        }
+        if !contains.is_empty() {
+            filters.push(CliqueFilter::Contains(contains.to_owned()));
+        }
+
+        CliqueQuery { k, filters, list }
+    }
+
+    fn run<'a>(&self, g: &Graph<'a>) -> Vec<Vec<&'a str>> {
+        let mut cliques = k_cliques(g, self.k);
+        cliques.retain(|clique| self.filters.iter().all(|f| f.matches(clique)));
+
+        if self.list {
+            for clique in &cliques {
+                println!("{}", clique.join(","));
+            }
+        }
+
+        cliques
+    }
+}
+
+pub fn run(list_cliques: bool, query: Option<CliqueQuery>, emit: Option<&str>, stats: bool) {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 23).unwrap();
     let contents: Vec<(&str, &str)> = contents
@@ -60,80 +127,460 @@ pub fn run() {
 
     let g = Graph::new(&contents);
     part1(&mut writer, &g);
-    part2(&mut writer, &g);
+    let max_clique = part2(&mut writer, &g, list_cliques);
+
+    if let Some(query) = query {
+        let cliques = query.run(&g);
+        println!("{} matching cliques of size {}", cliques.len(), query.k);
+    }
+
+    if let Some(format) = emit {
+        emit_graph(&g, &max_clique, format);
+    }
+
+    if stats {
+        print_stats(&g);
+    }
 }
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, g: &Graph) {
-    let mut triplets: HashSet<Vec<&str>> = HashSet::new();
-    for (name, node) in g.iter() {
-        for neighbour_name in &node.neighbours {
-            let neighbour_node = g.get(neighbour_name).unwrap();
-            for neighbour2_name in &neighbour_node.neighbours {
-                let neighbour2_node = g.get(neighbour2_name).unwrap();
-                if neighbour2_node.neighbours.contains(name) {
-                    let mut v = vec![*name, *neighbour_name, *neighbour2_name];
-                    v.sort();
-                    triplets.insert(v);
+    let query = CliqueQuery {
+        k: 3,
+        filters: vec![CliqueFilter::Prefix("t".into())],
+        list: true,
+    };
+
+    let total = query.run(g).len();
+    printwriteln!(writer, "part 1: {}", total).unwrap();
+}
+
+/// Every clique of size k, as sorted names. Cliques are only ever extended with names that sort
+/// after all of their current members, so each one is found exactly once.
+fn k_cliques<'a>(g: &Graph<'a>, k: usize) -> Vec<Vec<&'a str>> {
+    let mut names: Vec<&str> = g.keys().cloned().collect();
+    names.sort();
+
+    let mut cliques = Vec::new();
+    for name in names {
+        let candidates = g
+            .get(name)
+            .unwrap()
+            .neighbours
+            .iter()
+            .filter(|&&n| n > name)
+            .cloned()
+            .collect();
+        extend_clique_rec(g, &mut vec![name], candidates, k, &mut cliques);
+    }
+
+    cliques
+}
+
+/// `candidates` are the computers connected to every member of `clique` that sort after them.
+fn extend_clique_rec<'a>(
+    g: &Graph<'a>,
+    clique: &mut Vec<&'a str>,
+    candidates: Vec<&'a str>,
+    k: usize,
+    cliques: &mut Vec<Vec<&'a str>>,
+) {
+    if clique.len() == k {
+        cliques.push(clique.clone());
+        return;
+    }
+
+    if clique.len() + candidates.len() < k {
+        return;
+    }
+
+    let mut candidates = candidates;
+    candidates.sort();
+    for (i, &next) in candidates.iter().enumerate() {
+        let neighbours = &g.get(next).unwrap().neighbours;
+        let next_candidates = candidates[(i + 1)..]
+            .iter()
+            .filter(|n| neighbours.contains(*n))
+            .cloned()
+            .collect();
+
+        clique.push(next);
+        extend_clique_rec(g, clique, next_candidates, k, cliques);
+        clique.pop();
+    }
+}
+
+fn part2<'a, W: Write>(
+    writer: &mut BufWriter<W>,
+    g: &Graph<'a>,
+    list_cliques: bool,
+) -> Vec<&'a str> {
+    let dense = DenseGraph::new(g);
+    let mut maximal_cliques = Vec::new();
+    bron_kerbosch_degeneracy(&dense, &mut maximal_cliques);
+
+    let mut maximal_cliques: Vec<Vec<&str>> = maximal_cliques
+        .iter()
+        .map(|clique| {
+            let mut names: Vec<&str> = clique.iter().map(|v| dense.names[v]).collect();
+            names.sort();
+            names
+        })
+        .collect();
+    maximal_cliques.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+    if list_cliques {
+        for clique in &maximal_cliques {
+            println!("{}: {}", clique.len(), clique.join(","));
+        }
+    }
+
+    printwriteln!(writer, "part 2: {}", maximal_cliques[0].join(",")).unwrap();
+
+    maximal_cliques.swap_remove(0)
+}
+
+/// Every connection once, as a sorted pair of sorted names.
+fn edges<'a>(g: &Graph<'a>) -> Vec<(&'a str, &'a str)> {
+    let mut edges: Vec<(&str, &str)> = g
+        .iter()
+        .flat_map(|(&name, node)| {
+            node.neighbours
+                .iter()
+                .filter(move |&&n| name < n)
+                .map(move |&n| (name, n))
+        })
+        .collect();
+    edges.sort();
+
+    edges
+}
+
+/// Writes the graph to outputs/23.dot or outputs/23.graphml, with the computers and connections
+/// of `highlight` marked.
+fn emit_graph(g: &Graph, highlight: &[&str], format: &str) {
+    let mut names: Vec<&str> = g.keys().cloned().collect();
+    names.sort();
+    let edges = edges(g);
+    let highlighted = |name: &str| highlight.contains(&name);
+
+    match format {
+        "dot" => {
+            let mut writer = BufWriter::new(File::create("outputs/23.dot").unwrap());
+            writeln!(writer, "graph lan {{").unwrap();
+            for name in &names {
+                if highlighted(name) {
+                    writeln!(writer, "    {} [style=filled, fillcolor=red];", name).unwrap();
+                } else {
+                    writeln!(writer, "    {};", name).unwrap();
+                }
+            }
+            for (a, b) in &edges {
+                if highlighted(a) && highlighted(b) {
+                    writeln!(writer, "    {} -- {} [color=red, penwidth=2];", a, b).unwrap();
+                } else {
+                    writeln!(writer, "    {} -- {};", a, b).unwrap();
                 }
             }
+            writeln!(writer, "}}").unwrap();
         }
+        "graphml" => {
+            let mut writer = BufWriter::new(File::create("outputs/23.graphml").unwrap());
+            writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+            writeln!(
+                writer,
+                r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+            )
+            .unwrap();
+            writeln!(
+                writer,
+                r#"  <key id="clique" for="all" attr.name="max_clique" attr.type="boolean">"#
+            )
+            .unwrap();
+            writeln!(writer, "    <default>false</default>").unwrap();
+            writeln!(writer, "  </key>").unwrap();
+            writeln!(writer, r#"  <graph id="lan" edgedefault="undirected">"#).unwrap();
+            for name in &names {
+                if highlighted(name) {
+                    writeln!(
+                        writer,
+                        r#"    <node id="{}"><data key="clique">true</data></node>"#,
+                        name
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(writer, r#"    <node id="{}"/>"#, name).unwrap();
+                }
+            }
+            for (a, b) in &edges {
+                if highlighted(a) && highlighted(b) {
+                    writeln!(
+                        writer,
+                        r#"    <edge source="{}" target="{}"><data key="clique">true</data></edge>"#,
+                        a, b
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(writer, r#"    <edge source="{}" target="{}"/>"#, a, b).unwrap();
+                }
+            }
+            writeln!(writer, "  </graph>").unwrap();
+            writeln!(writer, "</graphml>").unwrap();
+        }
+        _ => panic!("unknown graph format {}, expected dot or graphml", format),
     }
+}
+
+fn print_stats(g: &Graph) {
+    let dense = DenseGraph::new(g);
+    let n = dense.names.len();
+    let degrees: Vec<usize> = dense.neighbours.iter().map(|nb| nb.len()).collect();
+
+    println!("nodes: {}", n);
+    println!("edges: {}", degrees.iter().sum::<usize>() / 2);
+
+    let mut distribution = BTreeMap::new();
+    for d in &degrees {
+        *distribution.entry(d).or_insert(0) += 1;
+    }
+    println!("degree distribution:");
+    for (d, count) in distribution {
+        println!("  {:>3}: {}", d, count);
+    }
+
+    let mut component_sizes = components(&dense);
+    component_sizes.sort_by(|a, b| b.cmp(a));
+    println!(
+        "components: {} (sizes {})",
+        component_sizes.len(),
+        component_sizes
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
 
-    let mut total = 0;
-    for triplet in triplets {
-        println!("{},{},{}", triplet[0], triplet[1], triplet[2]);
+    // triangles through v are the connections among v's neighbours
+    let triangles_at: Vec<usize> = (0..n)
+        .map(|v| {
+            dense.neighbours[v]
+                .iter()
+                .map(|u| dense.neighbours[v].intersection(&dense.neighbours[u]).len())
+                .sum::<usize>()
+                / 2
+        })
+        .collect();
+    println!("triangles: {}", triangles_at.iter().sum::<usize>() / 3);
 
-        if triplet[0].starts_with('t') || triplet[1].starts_with('t') || triplet[2].starts_with('t')
-        {
-            total += 1;
+    let clustering: f64 = (0..n)
+        .map(|v| {
+            let d = degrees[v];
+            if d < 2 {
+                0.0
+            } else {
+                triangles_at[v] as f64 / (d * (d - 1) / 2) as f64
+            }
+        })
+        .sum::<f64>()
+        / n as f64;
+    println!("average clustering coefficient: {:.4}", clustering);
+}
+
+/// The size of each connected component.
+fn components(dense: &DenseGraph) -> Vec<usize> {
+    let n = dense.names.len();
+    let mut seen = vec![false; n];
+    let mut sizes = Vec::new();
+    for start in 0..n {
+        if seen[start] {
+            continue;
+        }
+
+        seen[start] = true;
+        let mut stack = vec![start];
+        let mut size = 0;
+        while let Some(v) = stack.pop() {
+            size += 1;
+            for u in dense.neighbours[v].iter() {
+                if !seen[u] {
+                    seen[u] = true;
+                    stack.push(u);
+                }
+            }
         }
+        sizes.push(size);
     }
 
-    printwriteln!(writer, "part 1: {}", total).unwrap();
+    sizes
 }
 
-fn part2<W: Write>(writer: &mut BufWriter<W>, g: &Graph) {
-    let mut maximal_cliques = Vec::new();
-    bron_kerbosch_basic(
-        g,
-        HashSet::new(),
-        g.keys().cloned().collect(),
-        HashSet::new(),
-        &mut maximal_cliques,
-    );
+/// A fixed-size set of dense vertex ids.
+#[derive(Clone)]
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn new(n: usize) -> BitSet {
+        BitSet(vec![0; n.div_ceil(64)])
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.0[i / 64] &= !(1 << (i % 64));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|&w| w == 0)
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    fn intersection(&self, other: &BitSet) -> BitSet {
+        BitSet(self.0.iter().zip(&other.0).map(|(a, b)| a & b).collect())
+    }
+
+    fn difference(&self, other: &BitSet) -> BitSet {
+        BitSet(self.0.iter().zip(&other.0).map(|(a, b)| a & !b).collect())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, &w)| {
+            let mut w = w;
+            iter::from_fn(move || {
+                if w == 0 {
+                    return None;
+                }
+                let bit = w.trailing_zeros() as usize;
+                w &= w - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+}
+
+/// The graph with its computer names mapped to dense ids and bitset adjacency.
+struct DenseGraph<'a> {
+    names: Vec<&'a str>,
+    neighbours: Vec<BitSet>,
+}
+
+impl<'a> DenseGraph<'a> {
+    fn new(g: &Graph<'a>) -> DenseGraph<'a> {
+        let mut names: Vec<&str> = g.keys().cloned().collect();
+        names.sort();
+        let ids: HashMap<&str, usize> = names.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+
+        let mut neighbours = vec![BitSet::new(names.len()); names.len()];
+        for (i, name) in names.iter().enumerate() {
+            for neighbour in &g.get(name).unwrap().neighbours {
+                neighbours[i].insert(ids[neighbour]);
+            }
+        }
+
+        DenseGraph { names, neighbours }
+    }
+
+    /// Repeatedly removes a vertex of minimum remaining degree, using buckets by degree.
+    fn degeneracy_order(&self) -> Vec<usize> {
+        let n = self.names.len();
+        let mut degree: Vec<usize> = self.neighbours.iter().map(|nb| nb.len()).collect();
+        let max_degree = degree.iter().cloned().max().unwrap_or(0);
+        let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); max_degree + 1];
+        for (v, &d) in degree.iter().enumerate() {
+            buckets[d].push(v);
+        }
+
+        let mut removed = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+        let mut d: usize = 0;
+        while order.len() < n {
+            // a vertex's degree only ever drops by one at a time, so the minimum can only fall
+            // to one below the last one
+            d = d.saturating_sub(1);
+            while buckets[d].is_empty() {
+                d += 1;
+            }
 
-    let maximum_clique = maximal_cliques.iter().max_by_key(|e| e.len()).unwrap();
-    let mut maximum_clique_vec: Vec<&str> = maximum_clique.iter().cloned().collect();
-    maximum_clique_vec.sort();
+            // buckets can hold stale entries for vertices whose degree has since dropped
+            let v = buckets[d].pop().unwrap();
+            if removed[v] || degree[v] != d {
+                continue;
+            }
+
+            removed[v] = true;
+            order.push(v);
+            for u in self.neighbours[v].iter() {
+                if !removed[u] {
+                    degree[u] -= 1;
+                    buckets[degree[u]].push(u);
+                }
+            }
+        }
+
+        order
+    }
+}
+
+/// https://en.wikipedia.org/wiki/Bron%E2%80%93Kerbosch_algorithm#With_vertex_ordering
+fn bron_kerbosch_degeneracy(g: &DenseGraph, maximal_cliques: &mut Vec<BitSet>) {
+    let n = g.names.len();
+    let mut p = BitSet::new(n);
+    for v in 0..n {
+        p.insert(v);
+    }
+    let mut x = BitSet::new(n);
+
+    for v in g.degeneracy_order() {
+        let mut r = BitSet::new(n);
+        r.insert(v);
+        bron_kerbosch_pivot(
+            g,
+            r,
+            p.intersection(&g.neighbours[v]),
+            x.intersection(&g.neighbours[v]),
+            maximal_cliques,
+        );
 
-    printwriteln!(writer, "part 2: {}", maximum_clique_vec.join(",")).unwrap();
+        p.remove(v);
+        x.insert(v);
+    }
 }
 
-/// https://en.wikipedia.org/wiki/Bron%E2%80%93Kerbosch_algorithm#Without_pivoting
-fn bron_kerbosch_basic<'a>(
-    g: &'a Graph<'a>,
-    r: HashSet<&'a str>,
-    p: HashSet<&'a str>,
-    mut x: HashSet<&'a str>,
-    maximal_cliques: &mut Vec<HashSet<&'a str>>,
+/// https://en.wikipedia.org/wiki/Bron%E2%80%93Kerbosch_algorithm#With_pivoting
+fn bron_kerbosch_pivot(
+    g: &DenseGraph,
+    r: BitSet,
+    mut p: BitSet,
+    mut x: BitSet,
+    maximal_cliques: &mut Vec<BitSet>,
 ) {
     if p.is_empty() && x.is_empty() {
         maximal_cliques.push(r);
         return;
     }
 
-    let mut next_p = p.clone();
-    for v in p.iter() {
-        let neighbours = &g.get(v).unwrap().neighbours;
-        bron_kerbosch_basic(
+    // the pivot with the most neighbours in p leaves the fewest branches to explore
+    let pivot = p
+        .iter()
+        .chain(x.iter())
+        .max_by_key(|&u| p.intersection(&g.neighbours[u]).len())
+        .unwrap();
+
+    for v in p.difference(&g.neighbours[pivot]).iter() {
+        let mut next_r = r.clone();
+        next_r.insert(v);
+        bron_kerbosch_pivot(
             g,
-            r.union(&HashSet::from([*v])).cloned().collect(),
-            next_p.intersection(neighbours).cloned().collect(),
-            x.intersection(neighbours).cloned().collect(),
+            next_r,
+            p.intersection(&g.neighbours[v]),
+            x.intersection(&g.neighbours[v]),
             maximal_cliques,
         );
 
-        next_p.remove(v);
+        p.remove(v);
         x.insert(v);
     }
 }
@@ -8,8 +8,155 @@ use clap::Parser;
 struct Cli {
     /// The day to run.
     day: u8,
+
+    /// Day 2: how many bad levels the problem dampener can remove from a report.
+    #[arg(long, default_value_t = 1)]
+    tolerance: usize,
+
+    /// Day 3: more instructions to look for, as name:effect[:arity] where the effect is product,
+    /// sum, enable or disable (e.g. add:sum,mul3:product:3).
+    #[arg(long, value_delimiter = ',')]
+    extra_instructions: Vec<String>,
+
+    /// Day 3: list every instruction found, with its byte offsets and whether it counted, and the
+    /// enabled and disabled spans.
+    #[arg(long)]
+    audit: bool,
+
+    /// Day 4: words to search for, in any direction.
+    #[arg(long, value_delimiter = ',')]
+    words: Vec<String>,
+
+    /// Day 4: a pattern to search for, rotated or flipped, with rows separated by / and . matching
+    /// any letter (e.g. M.S/.A./M.S).
+    #[arg(long = "pattern")]
+    patterns: Vec<String>,
+
+    /// Day 4: list where each word or pattern was found.
+    #[arg(long)]
+    list_matches: bool,
+
+    /// Day 4: show where the matches are (dots, colour or heatmap).
+    #[arg(long)]
+    highlight: Option<String>,
+
+    /// Day 5: explain what's wrong with each incorrectly ordered update (table or json).
+    #[arg(long)]
+    violations: Option<String>,
+
+    /// Day 6: draw the guard's route with an obstruction added at x,y.
+    #[arg(long, value_delimiter = ',')]
+    show_loop: Vec<usize>,
+
+    /// Day 7: also try the equations with this set of operators (+, -, *, /, ^, || or ||k for
+    /// concatenation in base k).
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    operators: Vec<String>,
+
+    /// Day 12: sort the table of regions by plant, area, perimeter, sides or holes.
+    #[arg(long)]
+    sort_by: Option<String>,
+
+    /// Day 12: draw the garden to outputs/12.<format>, with each region in its own colour and
+    /// its fences outlined (svg or ppm).
+    #[arg(long)]
+    image: Option<String>,
+
+    /// Day 13: the token cost of each button, in the order the buttons are listed.
+    #[arg(long, value_delimiter = ',', default_value = "3,1")]
+    button_costs: Vec<u64>,
+
+    /// Day 16: the cost of stepping forward one tile.
+    #[arg(long, default_value_t = 1)]
+    step_cost: u32,
+
+    /// Day 16: the cost of turning 90 degrees (45 degrees with --diagonal).
+    #[arg(long, default_value_t = 1000)]
+    turn_cost: u32,
+
+    /// Day 16: also allow turning around in a single move, for this cost.
+    #[arg(long)]
+    u_turn_cost: Option<u32>,
+
+    /// Day 16: allow facing and moving diagonally.
+    #[arg(long)]
+    diagonal: bool,
+
+    /// Day 16: the direction the reindeer starts off facing.
+    #[arg(long, default_value = "right")]
+    start_facing: String,
+
+    /// Day 16: the direction the reindeer must be facing at the end (any if omitted).
+    #[arg(long)]
+    end_facing: Option<String>,
+
+    /// Day 16: list the k cheapest route costs, with how many distinct routes cost each.
+    /// Day 23: look for cliques of k computers.
+    #[arg(long)]
+    k: Option<usize>,
+
+    /// Day 18: the width of the memory grid (inferred from the input if omitted).
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Day 18: the height of the memory grid (inferred from the input if omitted).
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Day 18: the number of bytes that have fallen for part 1.
+    #[arg(long)]
+    bytes: Option<usize>,
+
+    /// Day 20: the longest cheat allowed in part 2, in picoseconds.
+    #[arg(long, default_value_t = 20)]
+    cheat_len: i32,
+
+    /// Day 20: only count cheats that save at least this many picoseconds.
+    #[arg(long, default_value_t = 100)]
+    min_save: i32,
+
+    /// Day 22: rank the n best change sequences.
+    #[arg(long)]
+    top: Option<usize>,
+
+    /// Day 22: print each buyer's secret after this many steps.
+    #[arg(long)]
+    secret_steps: Option<u64>,
+
+    /// Day 22: print the length of the cycle each buyer's secret is on.
+    #[arg(long)]
+    cycle_length: bool,
+
+    /// Day 23: list every maximal clique along with its size.
+    #[arg(long)]
+    list_cliques: bool,
+
+    /// Day 23: only match cliques with a computer whose name starts with this.
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Day 23: only match cliques with a computer whose name matches this regex.
+    #[arg(long)]
+    name_regex: Option<String>,
+
+    /// Day 23: only match cliques containing all of these computers.
+    #[arg(long, value_delimiter = ',')]
+    contains: Vec<String>,
+
+    /// Day 23: list the matching cliques of k computers rather than just counting them.
+    #[arg(long)]
+    list_k_cliques: bool,
+
+    /// Day 23: write the network to outputs/23.<format> with the largest clique highlighted (dot
+    /// or graphml).
+    #[arg(long)]
+    emit: Option<String>,
+
+    /// Day 23: print statistics about the network.
+    #[arg(long)]
+    stats: bool,
 }
 
 fn main() {
-    days::run(Cli::parse().day);
+    days::run(&Cli::parse());
 }